@@ -1,46 +1,34 @@
 extern crate dhcp4r;
 extern crate time;
 
-use dhcp4r::{options, packet, server};
-use std::net::{Ipv4Addr, UdpSocket};
+use dhcp4r::options;
+use dhcp4r::packet::PacketView;
+use std::net::UdpSocket;
 
 fn main() {
-    server::Server::serve(
-        UdpSocket::bind("0.0.0.0:67").unwrap(),
-        Ipv4Addr::new(0, 0, 0, 0),
-        Ipv4Addr::new(0, 0, 0, 0),
-        MyServer {},
-    );
-}
-
-struct MyServer {}
+    let socket = UdpSocket::bind("0.0.0.0:67").unwrap();
+    let mut in_buf: [u8; 1500] = [0; 1500];
 
-impl server::Handler for MyServer {
-    fn handle_request(&mut self, _: &server::Server, in_packet: packet::Packet) {
-        match in_packet.message_type() {
-            Ok(options::MessageType::Request) => {
-                let req_ip = match in_packet.option(options::REQUESTED_IP_ADDRESS) {
-                    Some(options::DhcpOption::RequestedIpAddress(x)) => x.clone(),
-                    _ => in_packet.ciaddr,
-                };
-                println!(
-                    "{}\t{}\t{}\tOnline",
-                    time::OffsetDateTime::try_now_local()
-                        .unwrap()
-                        .format("%Y-%m-%dT%H:%M:%S"),
-                    chaddr(&in_packet.chaddr),
-                    Ipv4Addr::from(req_ip)
-                );
-            }
-            _ => {}
+    // Only a couple of fields are needed, so read them straight from the buffer.
+    loop {
+        let (l, _) = socket.recv_from(&mut in_buf).unwrap();
+        let in_packet = match PacketView::new(&in_buf[..l]) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        if let Some(options::MessageType::Request) = in_packet.message_type() {
+            let req_ip = match in_packet.option(options::REQUESTED_IP_ADDRESS) {
+                Some(options::DhcpOption::RequestedIpAddress(x)) => x,
+                _ => in_packet.ciaddr(),
+            };
+            println!(
+                "{}\t{}\t{}\tOnline",
+                time::OffsetDateTime::try_now_local()
+                    .unwrap()
+                    .format("%Y-%m-%dT%H:%M:%S"),
+                in_packet.chaddr(),
+                req_ip
+            );
         }
     }
 }
-
-/// Formats byte array machine address into hex pairs separated by colons.
-/// Array must be at least one byte long.
-fn chaddr(a: &[u8]) -> String {
-    a[1..].iter().fold(format!("{:02x}", a[0]), |acc, &b| {
-        format!("{}:{:02x}", acc, &b)
-    })
-}
@@ -0,0 +1,23 @@
+#![no_main]
+
+use dhcp4r::packet::{DecodeMode, Packet, PacketView};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Packet::decode(data, DecodeMode::Strict);
+    if let Ok(view) = PacketView::new(data) {
+        for (code, _) in view.raw_options() {
+            let _ = view.option(code);
+        }
+        let _ = view.message_type();
+    }
+
+    // Whatever decodes must encode and decode back to the same packet.
+    if let Ok(p) = Packet::from(data) {
+        if data.len() < 60000 {
+            let mut buf = vec![0; u16::MAX as usize];
+            let encoded = p.encode_with_limit(&mut buf, u16::MAX).unwrap();
+            assert_eq!(Packet::from(encoded).unwrap(), p);
+        }
+    }
+});
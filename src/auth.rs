@@ -0,0 +1,307 @@
+//! DHCP authentication (RFC 3118) as FORCERENEW needs it. The server hands each client
+//! a key in its DHCPACK, and later signs FORCERENEW messages to that client with
+//! HMAC-MD5 keyed by it. Clients ignore a FORCERENEW that is not signed.
+//!
+//! The key is a reconfigure key (RFC 3315, section 21.5) for clients that ask for one with
+//! an Authentication option, or a nonce (RFC 6704) for those that send Forcerenew Nonce
+//! Capable. Both use the same protocol and information types, so `sign` and `verify`
+//! serve either.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+
+use crate::options::*;
+use crate::packet::PacketView;
+
+pub const NONCE_LEN: usize = 16;
+
+/// Authentication information of a signed FORCERENEW: type octet and digest.
+const DIGEST_INFO_LEN: usize = 1 + 16;
+
+/// Protocol, algorithm, RDM and replay detection precede the authentication information.
+const INFO_OFFSET: usize = 11;
+
+/// MD5 digest (RFC 1321). Only for HMAC-MD5, which RFC 6704 requires.
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    // The table T of RFC 1321, section 3.4.
+    const K: [u32; 64] = [
+        0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+        0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+        0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+        0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+        0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+        0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+        0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+        0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+        0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+        0xeb86d391,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64).wrapping_mul(8).to_le_bytes().iter());
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in message.chunks_exact(64) {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(chunk.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for (i, &k) in K.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k).wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d].iter()) {
+            *s = s.wrapping_add(*v);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, s) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&s.to_le_bytes());
+    }
+    digest
+}
+
+/// HMAC (RFC 2104) with MD5.
+pub fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..16].copy_from_slice(&md5(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner: Vec<u8> = block
+        .iter()
+        .map(|b| b ^ 0x36)
+        .chain(data.iter().copied())
+        .collect();
+    let outer: Vec<u8> = block
+        .iter()
+        .map(|b| b ^ 0x5c)
+        .chain(md5(&inner).iter().copied())
+        .collect();
+    md5(&outer)
+}
+
+/// A fresh nonce for a lease, read from the operating system's random source. Fails
+/// where there is no `/dev/urandom`, since nonces and keys must not be guessable.
+pub fn generate_nonce() -> io::Result<[u8; NONCE_LEN]> {
+    let mut nonce = [0; NONCE_LEN];
+    File::open("/dev/urandom")?.read_exact(&mut nonce)?;
+    Ok(nonce)
+}
+
+/// The Authentication option that hands `nonce` to the client, in a DHCPACK.
+pub fn nonce_option(nonce: &[u8; NONCE_LEN], replay_detection: u64) -> DhcpOption {
+    DhcpOption::Authentication(Authentication {
+        protocol: AUTH_PROTOCOL_FORCERENEW_NONCE,
+        algorithm: AUTH_ALGORITHM_HMAC_MD5,
+        rdm: AUTH_RDM_MONOTONIC,
+        replay_detection,
+        information: [&[FORCERENEW_NONCE_VALUE], &nonce[..]].concat(),
+    })
+}
+
+/// The Authentication option that hands a reconfigure `key` to the client, in a DHCPACK.
+/// `generate_nonce` makes a good key.
+pub fn reconfigure_key_option(key: &[u8; NONCE_LEN], replay_detection: u64) -> DhcpOption {
+    DhcpOption::Authentication(Authentication {
+        protocol: AUTH_PROTOCOL_RECONFIGURE_KEY,
+        algorithm: AUTH_ALGORITHM_HMAC_MD5,
+        rdm: AUTH_RDM_MONOTONIC,
+        replay_detection,
+        information: [&[RECONFIGURE_KEY_VALUE], &key[..]].concat(),
+    })
+}
+
+/// The Authentication option of a FORCERENEW before it is signed, with a zero digest
+/// for `sign` to fill in.
+pub fn unsigned_option(replay_detection: u64) -> DhcpOption {
+    DhcpOption::Authentication(Authentication {
+        protocol: AUTH_PROTOCOL_FORCERENEW_NONCE,
+        algorithm: AUTH_ALGORITHM_HMAC_MD5,
+        rdm: AUTH_RDM_MONOTONIC,
+        replay_detection,
+        information: [FORCERENEW_NONCE_HMAC_MD5; 1]
+            .iter()
+            .chain([0; 16].iter())
+            .copied()
+            .collect(),
+    })
+}
+
+/// Data of the Authentication option of an encoded message carrying the option of
+/// `unsigned_option`.
+fn signed_option(message: &[u8]) -> Option<&[u8]> {
+    let view = PacketView::new(message).ok()?;
+    let (_, data) = view
+        .raw_options()
+        .find(|&(code, _)| code == AUTHENTICATION)?;
+    match data {
+        [AUTH_PROTOCOL_FORCERENEW_NONCE, AUTH_ALGORITHM_HMAC_MD5, _, _, _, _, _, _, _, _, _, FORCERENEW_NONCE_HMAC_MD5, ..]
+            if data.len() == INFO_OFFSET + DIGEST_INFO_LEN =>
+        {
+            Some(data)
+        }
+        _ => None,
+    }
+}
+
+/// Where the digest is in an encoded message carrying the option of `unsigned_option`.
+fn digest_range(message: &[u8]) -> Option<Range<usize>> {
+    let data = signed_option(message)?;
+    let start = data.as_ptr() as usize - message.as_ptr() as usize + INFO_OFFSET + 1;
+    Some(start..start + 16)
+}
+
+/// HMAC-MD5 of the message with `hops`, `giaddr` and the digest zeroed, which relays
+/// may change on the way (RFC 3118, section 5).
+fn digest(message: &[u8], range: Range<usize>, nonce: &[u8; NONCE_LEN]) -> [u8; 16] {
+    let mut message = message.to_vec();
+    message[3] = 0;
+    message[24..28].copy_from_slice(&[0; 4]);
+    message[range].copy_from_slice(&[0; 16]);
+    hmac_md5(nonce, &message)
+}
+
+/// Fills in the digest of an encoded FORCERENEW carrying the option of `unsigned_option`.
+/// Returns false, leaving the message as it is, if there is no such option.
+pub fn sign(message: &mut [u8], nonce: &[u8; NONCE_LEN]) -> bool {
+    match digest_range(message) {
+        Some(range) => {
+            let digest = digest(message, range.clone(), nonce);
+            message[range].copy_from_slice(&digest);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether an encoded message was signed with `nonce`. See `verify_fresh` for checking
+/// the replay detection value too.
+pub fn verify(message: &[u8], nonce: &[u8; NONCE_LEN]) -> bool {
+    match digest_range(message) {
+        Some(range) => {
+            let expected = digest(message, range.clone(), nonce);
+            // Compare every octet, so the time taken does not tell how many matched.
+            message[range]
+                .iter()
+                .zip(expected.iter())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+        }
+        None => false,
+    }
+}
+
+/// Checks an encoded message as a client does: it must be signed with `key`, and its
+/// replay detection value must be greater than `last`, that of the last message accepted
+/// with the key. Returns the value to keep as `last` for the next one.
+pub fn verify_fresh(message: &[u8], key: &[u8; NONCE_LEN], last: Option<u64>) -> Option<u64> {
+    let data = signed_option(message)?;
+    let mut replay_detection = [0; 8];
+    replay_detection.copy_from_slice(&data[3..INFO_OFFSET]);
+    let replay_detection = u64::from_be_bytes(replay_detection);
+    match last {
+        Some(last) if replay_detection <= last => None,
+        _ if verify(message, key) => Some(replay_detection),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use crate::packet::{HardwareAddr, Packet};
+    use std::net::Ipv4Addr;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn md5_and_hmac_test_vectors() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hex(&md5(&[b'a'; 1000])), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+        // RFC 2104, appendix.
+        assert_eq!(
+            hex(&hmac_md5(&[0x0b; 16], b"Hi There")),
+            "9294727a3638bb1c13f48ef8158bfc9d"
+        );
+        assert_eq!(
+            hex(&hmac_md5(b"Jefe", b"what do ya want for nothing?")),
+            "750c783e6ab0b503eaa86e310a5db738"
+        );
+        assert_ne!(generate_nonce().unwrap(), generate_nonce().unwrap());
+    }
+
+    #[test]
+    fn signed_forcerenew() {
+        let nonce = generate_nonce().unwrap();
+        let p = PacketBuilder::new(HardwareAddr::ethernet([0; 6]))
+            .reply(true)
+            .ciaddr(Ipv4Addr::new(192, 168, 0, 10))
+            .message_type(MessageType::ForceRenew)
+            .option(unsigned_option(7))
+            .build();
+        let mut buf = [0; 576];
+        let len = p.encode(&mut buf).len();
+        let message = &mut buf[..len];
+        assert!(!verify(message, &nonce));
+        assert!(sign(message, &nonce));
+        assert!(verify(message, &nonce));
+        assert!(!verify(message, &generate_nonce().unwrap()));
+
+        // Relays may change hops and giaddr.
+        message[3] = 2;
+        message[24] = 10;
+        assert!(verify(message, &nonce));
+        message[16] ^= 1;
+        assert!(!verify(message, &nonce));
+
+        let decoded = Packet::from(message).unwrap();
+        match decoded.option(AUTHENTICATION) {
+            Some(DhcpOption::Authentication(auth)) => {
+                assert_eq!(auth.replay_detection, 7);
+                assert_eq!(auth.information.len(), DIGEST_INFO_LEN);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            nonce_option(&nonce, 1).to_raw().data[INFO_OFFSET..],
+            [&[FORCERENEW_NONCE_VALUE], &nonce[..]].concat()[..]
+        );
+        assert_eq!(
+            reconfigure_key_option(&nonce, 1).to_raw().data,
+            nonce_option(&nonce, 1).to_raw().data
+        );
+
+        // Clients only accept messages newer than the last.
+        message[16] ^= 1;
+        assert_eq!(verify_fresh(message, &nonce, None), Some(7));
+        assert_eq!(verify_fresh(message, &nonce, Some(6)), Some(7));
+        assert_eq!(verify_fresh(message, &nonce, Some(7)), None);
+        assert_eq!(
+            verify_fresh(message, &generate_nonce().unwrap(), Some(6)),
+            None
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! Construction of client and server messages without writing out every `Packet` field.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::Ipv4Addr;
+use std::time::SystemTime;
+
+use crate::options::*;
+use crate::packet::*;
+
+/// Builds a `Packet` one field at a time. Every field starts out zero, with no options.
+#[derive(Clone, Debug)]
+pub struct PacketBuilder {
+    packet: Packet,
+}
+
+impl PacketBuilder {
+    /// Starts a BOOTREQUEST from the client with hardware address `chaddr`.
+    pub fn new(chaddr: HardwareAddr) -> PacketBuilder {
+        PacketBuilder {
+            packet: Packet {
+                reply: false,
+                hops: 0,
+                xid: 0,
+                secs: 0,
+                flags: 0,
+                ciaddr: Ipv4Addr::UNSPECIFIED,
+                yiaddr: Ipv4Addr::UNSPECIFIED,
+                siaddr: Ipv4Addr::UNSPECIFIED,
+                giaddr: Ipv4Addr::UNSPECIFIED,
+                chaddr,
+                sname: [0; 64],
+                file: [0; 128],
+                vend: None,
+                options: Vec::new(),
+            },
+        }
+    }
+
+    /// Starts a BOOTREPLY to `request`, copying the fields a server must echo
+    /// back (RFC 2131 table 3): `xid`, `flags`, `giaddr` and `chaddr`.
+    pub fn reply_to(request: &Packet) -> PacketBuilder {
+        PacketBuilder::new(request.chaddr)
+            .reply(true)
+            .xid(request.xid)
+            .flags(request.flags)
+            .giaddr(request.giaddr)
+    }
+
+    pub fn reply(mut self, reply: bool) -> Self {
+        self.packet.reply = reply;
+        self
+    }
+
+    pub fn hops(mut self, hops: u8) -> Self {
+        self.packet.hops = hops;
+        self
+    }
+
+    pub fn xid(mut self, xid: u32) -> Self {
+        self.packet.xid = xid;
+        self
+    }
+
+    pub fn secs(mut self, secs: u16) -> Self {
+        self.packet.secs = secs;
+        self
+    }
+
+    pub fn flags(mut self, flags: u16) -> Self {
+        self.packet.flags = flags;
+        self
+    }
+
+    pub fn broadcast(mut self, broadcast: bool) -> Self {
+        self.packet.set_broadcast(broadcast);
+        self
+    }
+
+    pub fn ciaddr(mut self, ciaddr: Ipv4Addr) -> Self {
+        self.packet.ciaddr = ciaddr;
+        self
+    }
+
+    pub fn yiaddr(mut self, yiaddr: Ipv4Addr) -> Self {
+        self.packet.yiaddr = yiaddr;
+        self
+    }
+
+    pub fn siaddr(mut self, siaddr: Ipv4Addr) -> Self {
+        self.packet.siaddr = siaddr;
+        self
+    }
+
+    pub fn giaddr(mut self, giaddr: Ipv4Addr) -> Self {
+        self.packet.giaddr = giaddr;
+        self
+    }
+
+    pub fn chaddr(mut self, chaddr: HardwareAddr) -> Self {
+        self.packet.chaddr = chaddr;
+        self
+    }
+
+    /// Sets the server host name.
+    ///
+    /// # Panics
+    ///
+    /// If `sname` does not leave room for the terminating null in the 64 byte field.
+    pub fn sname(mut self, sname: &str) -> Self {
+        assert!(sname.len() < 64, "sname longer than 63 bytes");
+        self.packet.sname = [0; 64];
+        self.packet.sname[..sname.len()].copy_from_slice(sname.as_bytes());
+        self
+    }
+
+    /// Sets the boot file name.
+    ///
+    /// # Panics
+    ///
+    /// If `file` does not leave room for the terminating null in the 128 byte field.
+    pub fn file(mut self, file: &str) -> Self {
+        assert!(file.len() < 128, "file longer than 127 bytes");
+        self.packet.file = [0; 128];
+        self.packet.file[..file.len()].copy_from_slice(file.as_bytes());
+        self
+    }
+
+    /// Sends RFC 1497 vendor extensions in place of the DHCP options, as a BOOTP packet.
+    pub fn vend(mut self, vend: Vec<u8>) -> Self {
+        self.packet.vend = Some(vend);
+        self
+    }
+
+    /// Sets the DHCP message type, which always goes first among the options.
+    pub fn message_type(mut self, msg_type: MessageType) -> Self {
+        self.packet
+            .options
+            .retain(|o| o.code() != DHCP_MESSAGE_TYPE);
+        self.packet
+            .options
+            .insert(0, DhcpOption::DhcpMessageType(msg_type));
+        self
+    }
+
+    pub fn option(mut self, option: DhcpOption) -> Self {
+        self.packet.options.push(option);
+        self
+    }
+
+    pub fn options<I: IntoIterator<Item = DhcpOption>>(mut self, options: I) -> Self {
+        self.packet.options.extend(options);
+        self
+    }
+
+    pub fn build(self) -> Packet {
+        self.packet
+    }
+}
+
+/// Client messages with the fields and options RFC 2131 (table 5) asks for.
+impl Packet {
+    /// DHCPDISCOVER broadcast by a client looking for servers. InfiniBand clients
+    /// cannot receive unicast before they are configured and ask for broadcast replies.
+    pub fn discover(chaddr: HardwareAddr) -> Packet {
+        PacketBuilder::new(chaddr)
+            .xid(random_xid())
+            .broadcast(chaddr.htype() == HTYPE_INFINIBAND)
+            .message_type(MessageType::Discover)
+            .build()
+    }
+
+    /// DHCPREQUEST accepting `offer` in the SELECTING state. The server identifier
+    /// is copied from the offer, and left out if the offer lacks one.
+    pub fn request_selecting(offer: &Packet) -> Packet {
+        let server_id = offer.option(SERVER_IDENTIFIER).cloned();
+        PacketBuilder::new(offer.chaddr)
+            .xid(offer.xid)
+            .flags(offer.flags)
+            .message_type(MessageType::Request)
+            .option(DhcpOption::RequestedIpAddress(offer.yiaddr))
+            .options(server_id)
+            .build()
+    }
+
+    /// DHCPREQUEST unicast to the server that granted `ack`, to extend the lease (RENEWING state).
+    pub fn request_renewing(ack: &Packet) -> Packet {
+        PacketBuilder::new(ack.chaddr)
+            .xid(random_xid())
+            .ciaddr(ack.yiaddr)
+            .message_type(MessageType::Request)
+            .build()
+    }
+
+    /// DHCPDECLINE telling the server that granted `ack` the address is already in use.
+    pub fn decline(ack: &Packet) -> Packet {
+        let server_id = ack.option(SERVER_IDENTIFIER).cloned();
+        PacketBuilder::new(ack.chaddr)
+            .xid(random_xid())
+            .message_type(MessageType::Decline)
+            .option(DhcpOption::RequestedIpAddress(ack.yiaddr))
+            .options(server_id)
+            .build()
+    }
+
+    /// DHCPRELEASE giving back the address granted in `lease`, the server's DHCPACK.
+    pub fn release(lease: &Packet) -> Packet {
+        let server_id = lease.option(SERVER_IDENTIFIER).cloned();
+        PacketBuilder::new(lease.chaddr)
+            .xid(random_xid())
+            .ciaddr(lease.yiaddr)
+            .message_type(MessageType::Release)
+            .options(server_id)
+            .build()
+    }
+
+    /// DHCPINFORM asking for configuration only, by a client already using `ciaddr`.
+    pub fn inform(chaddr: HardwareAddr, ciaddr: Ipv4Addr) -> Packet {
+        PacketBuilder::new(chaddr)
+            .xid(random_xid())
+            .ciaddr(ciaddr)
+            .message_type(MessageType::Inform)
+            .build()
+    }
+}
+
+/// A transaction id that differs between calls and between processes.
+pub(crate) fn random_xid() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42];
+    const SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 1);
+    const OFFERED: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 10);
+
+    fn round_trip(p: &Packet) -> Packet {
+        let mut buf = [0; 1500];
+        Packet::from(p.encode(&mut buf)).unwrap()
+    }
+
+    #[test]
+    fn client_conversation() {
+        let discover = Packet::discover(HardwareAddr::ethernet(MAC));
+        assert!(!discover.reply);
+        assert!(!discover.broadcast());
+        assert_eq!(discover.message_type().unwrap(), MessageType::Discover);
+        assert_eq!(round_trip(&discover), discover);
+
+        let offer = PacketBuilder::reply_to(&discover)
+            .yiaddr(OFFERED)
+            .option(DhcpOption::ServerIdentifier(SERVER))
+            .message_type(MessageType::Offer)
+            .build();
+        assert!(offer.reply);
+        assert_eq!(offer.xid, discover.xid);
+        assert_eq!(offer.message_type().unwrap(), MessageType::Offer);
+        assert_eq!(offer.options[0].code(), DHCP_MESSAGE_TYPE);
+
+        let request = Packet::request_selecting(&offer);
+        assert_eq!(request.xid, discover.xid);
+        assert_eq!(request.ciaddr, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(
+            request.options,
+            vec![
+                DhcpOption::DhcpMessageType(MessageType::Request),
+                DhcpOption::RequestedIpAddress(OFFERED),
+                DhcpOption::ServerIdentifier(SERVER),
+            ]
+        );
+
+        let ack = PacketBuilder::reply_to(&request)
+            .message_type(MessageType::Ack)
+            .yiaddr(OFFERED)
+            .option(DhcpOption::ServerIdentifier(SERVER))
+            .build();
+
+        let renew = Packet::request_renewing(&ack);
+        assert_eq!(renew.ciaddr, OFFERED);
+        assert_eq!(renew.option(SERVER_IDENTIFIER), None);
+
+        let release = Packet::release(&ack);
+        assert_eq!(release.ciaddr, OFFERED);
+        assert_eq!(release.message_type().unwrap(), MessageType::Release);
+        assert_eq!(
+            release.option(SERVER_IDENTIFIER),
+            Some(&DhcpOption::ServerIdentifier(SERVER))
+        );
+        assert_eq!(round_trip(&release), release);
+
+        let decline = Packet::decline(&ack);
+        assert_eq!(decline.ciaddr, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(
+            decline.option(REQUESTED_IP_ADDRESS),
+            Some(&DhcpOption::RequestedIpAddress(OFFERED))
+        );
+
+        let inform = Packet::inform(HardwareAddr::ethernet(MAC), OFFERED);
+        assert_eq!(inform.ciaddr, OFFERED);
+        assert_eq!(inform.message_type().unwrap(), MessageType::Inform);
+    }
+
+    #[test]
+    fn infiniband_discover_asks_for_broadcast() {
+        let chaddr = HardwareAddr::new(HTYPE_INFINIBAND, &[]).unwrap();
+        assert!(Packet::discover(chaddr).broadcast());
+    }
+
+    #[test]
+    fn server_fields() {
+        let p = PacketBuilder::new(HardwareAddr::ethernet(MAC))
+            .reply(true)
+            .siaddr(SERVER)
+            .sname("tftp.example.com")
+            .file("pxelinux.0")
+            .build();
+        assert_eq!(&p.sname[..17], b"tftp.example.com\0");
+        assert_eq!(&p.file[..11], b"pxelinux.0\0");
+        assert_eq!(round_trip(&p), p);
+    }
+}
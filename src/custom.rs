@@ -0,0 +1,331 @@
+//! Definitions of options the crate does not know itself, such as the site-specific
+//! codes 224 to 254 (RFC 3942), so that they decode to values and show up by name.
+//!
+//! Definitions are kept in an `OptionRegistry`, like the `option foo code 224 = ...`
+//! statements of an ISC dhcpd configuration. They apply where the registry is passed:
+//! to `Packet::decode_with`, `PacketView::option_with`, the text format, or a server
+//! through `ServerConfig`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use crate::options;
+
+/// Data type of a custom option.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionType {
+    Ip,
+    /// One or more addresses.
+    IpArray,
+    U8,
+    U16,
+    U32,
+    /// A single octet of 0 or 1.
+    Bool,
+    /// UTF-8 text of at least one octet.
+    String,
+    /// Opaque data of at least one octet.
+    Bytes,
+    /// Values of each type in turn. All but the last field must have a fixed length.
+    Record(Vec<OptionType>),
+}
+
+impl OptionType {
+    /// Length of every value of this type, or `None` if it takes the rest of the option.
+    pub fn fixed_len(&self) -> Option<usize> {
+        match self {
+            OptionType::Ip | OptionType::U32 => Some(4),
+            OptionType::U16 => Some(2),
+            OptionType::U8 | OptionType::Bool => Some(1),
+            OptionType::IpArray | OptionType::String | OptionType::Bytes => None,
+            OptionType::Record(fields) => fields.iter().map(OptionType::fixed_len).sum(),
+        }
+    }
+
+    /// Whether values can be told apart from what follows them in a record.
+    fn is_decodable(&self) -> bool {
+        match self {
+            OptionType::Record(fields) => match fields.split_last() {
+                Some((last, init)) => {
+                    init.iter().all(|f| f.fixed_len().is_some()) && last.is_decodable()
+                }
+                None => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Value of a custom option, of the `OptionType` it was defined with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    Ip(Ipv4Addr),
+    IpArray(Vec<Ipv4Addr>),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    Record(Vec<OptionValue>),
+}
+
+impl OptionValue {
+    /// The type this is a value of.
+    pub fn option_type(&self) -> OptionType {
+        match self {
+            OptionValue::Ip(_) => OptionType::Ip,
+            OptionValue::IpArray(_) => OptionType::IpArray,
+            OptionValue::U8(_) => OptionType::U8,
+            OptionValue::U16(_) => OptionType::U16,
+            OptionValue::U32(_) => OptionType::U32,
+            OptionValue::Bool(_) => OptionType::Bool,
+            OptionValue::String(_) => OptionType::String,
+            OptionValue::Bytes(_) => OptionType::Bytes,
+            OptionValue::Record(x) => {
+                OptionType::Record(x.iter().map(OptionValue::option_type).collect())
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            OptionValue::Ip(x) => x.octets().to_vec(),
+            OptionValue::IpArray(x) => x.iter().flat_map(|a| a.octets()).collect(),
+            OptionValue::U8(x) => vec![*x],
+            OptionValue::U16(x) => x.to_be_bytes().to_vec(),
+            OptionValue::U32(x) => x.to_be_bytes().to_vec(),
+            OptionValue::Bool(x) => vec![*x as u8],
+            OptionValue::String(x) => x.as_bytes().to_vec(),
+            OptionValue::Bytes(x) => x.clone(),
+            OptionValue::Record(x) => x.iter().flat_map(OptionValue::to_bytes).collect(),
+        }
+    }
+}
+
+/// Formats values the way ISC dhcpd writes them: addresses in a list separated by commas,
+/// strings quoted, bytes as colon-separated hex and record fields separated by spaces.
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionValue::Ip(x) => write!(f, "{}", x),
+            OptionValue::IpArray(x) => {
+                for (i, addr) in x.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", addr)?;
+                }
+                Ok(())
+            }
+            OptionValue::U8(x) => write!(f, "{}", x),
+            OptionValue::U16(x) => write!(f, "{}", x),
+            OptionValue::U32(x) => write!(f, "{}", x),
+            OptionValue::Bool(x) => write!(f, "{}", x),
+            OptionValue::String(x) => write!(f, "{:?}", x),
+            OptionValue::Bytes(x) => {
+                for (i, b) in x.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(":")?;
+                    }
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+            OptionValue::Record(x) => {
+                for (i, value) in x.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptionDefinition {
+    pub code: u8,
+    pub name: String,
+    pub option_type: OptionType,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DefinitionError {
+    /// The code is PAD, END or an option the crate decodes itself.
+    BuiltIn(u8),
+    /// A record field other than the last has no fixed length.
+    UnsizedField,
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BuiltIn(code) => write!(f, "option {} is already defined", code),
+            Self::UnsizedField => write!(f, "only the last field of a record may vary in length"),
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+/// Custom option definitions by code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OptionRegistry {
+    definitions: BTreeMap<u8, OptionDefinition>,
+}
+
+/// A registry without definitions, for decoding without custom options.
+pub(crate) static NO_DEFINITIONS: OptionRegistry = OptionRegistry::new();
+
+impl OptionRegistry {
+    pub const fn new() -> OptionRegistry {
+        OptionRegistry {
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    /// Decodes option `code` as `option_type` and titles it `name`. Replaces any earlier
+    /// definition of `code`.
+    pub fn define(
+        &mut self,
+        code: u8,
+        name: impl Into<String>,
+        option_type: OptionType,
+    ) -> Result<(), DefinitionError> {
+        // 0 and 255 are PAD and END, which carry no data.
+        if code == 0 || code == 255 || options::title(code).is_some() {
+            return Err(DefinitionError::BuiltIn(code));
+        }
+        if !option_type.is_decodable() {
+            return Err(DefinitionError::UnsizedField);
+        }
+        self.definitions.insert(
+            code,
+            OptionDefinition {
+                code,
+                name: name.into(),
+                option_type,
+            },
+        );
+        Ok(())
+    }
+
+    /// Removes the definition of `code`, which then decodes as `Unrecognized` again.
+    pub fn undefine(&mut self, code: u8) -> Option<OptionDefinition> {
+        self.definitions.remove(&code)
+    }
+
+    pub fn definition(&self, code: u8) -> Option<&OptionDefinition> {
+        self.definitions.get(&code)
+    }
+
+    /// All custom options, by code.
+    pub fn definitions(&self) -> impl Iterator<Item = &OptionDefinition> {
+        self.definitions.values()
+    }
+
+    /// Title of option `code`, as `options::title` gives it or as it was defined with.
+    pub fn title(&self, code: u8) -> Option<&str> {
+        options::title(code).or_else(|| self.definition(code).map(|d| d.name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::*;
+    use crate::packet::{DecodeMode, HardwareAddr, Packet};
+
+    #[test]
+    fn custom_options_decode_by_definition() {
+        let mut registry = OptionRegistry::new();
+        let record = OptionType::Record(vec![OptionType::Ip, OptionType::U16, OptionType::String]);
+        registry.define(224, "Provisioning Server", record).unwrap();
+        registry.define(225, "Site Flag", OptionType::Bool).unwrap();
+        assert_eq!(registry.title(224), Some("Provisioning Server"));
+        assert_eq!(registry.title(SUBNET_MASK), Some("Subnet Mask"));
+        assert_eq!(title(224), None);
+
+        let server = DhcpOption::Custom(
+            224,
+            OptionValue::Record(vec![
+                OptionValue::Ip(Ipv4Addr::new(10, 0, 0, 5)),
+                OptionValue::U16(8443),
+                OptionValue::String("/boot".to_string()),
+            ]),
+        );
+        let mut p = Packet::discover(HardwareAddr::ethernet([0; 6]));
+        p.options.push(server.clone());
+        p.options.push(DhcpOption::Unrecognized(RawDhcpOption {
+            code: 225,
+            data: vec![2],
+        }));
+        let mut buf = [0; 1500];
+        let encoded = p.encode(&mut buf);
+        let decoded = Packet::decode_with(encoded, DecodeMode::Lenient, &registry).unwrap();
+        assert_eq!(decoded.option(224), Some(&server));
+        assert_eq!(server.to_raw().data, b"\x0a\0\0\x05\x20\xfb/boot");
+        // Malformed values are kept raw, as for built-in options.
+        assert!(matches!(
+            decoded.option(225),
+            Some(DhcpOption::Unrecognized(_))
+        ));
+        if let DhcpOption::Custom(_, value) = &server {
+            assert_eq!(value.to_string(), r#"10.0.0.5 8443 "/boot""#);
+        }
+
+        // Other registries, and packets decoded without one, know nothing of them.
+        let plain = Packet::from(encoded).unwrap();
+        assert!(matches!(
+            plain.option(224),
+            Some(DhcpOption::Unrecognized(_))
+        ));
+        let mut other = OptionRegistry::new();
+        other.define(224, "Other", OptionType::Bytes).unwrap();
+        let decoded = Packet::decode_with(encoded, DecodeMode::Lenient, &other).unwrap();
+        assert!(matches!(
+            decoded.option(224),
+            Some(DhcpOption::Custom(224, OptionValue::Bytes(_)))
+        ));
+
+        assert!(registry.undefine(225).is_some());
+        assert_eq!(registry.title(225), None);
+        assert_eq!(registry.definitions().count(), 1);
+    }
+
+    #[test]
+    fn definitions_are_checked() {
+        let mut registry = OptionRegistry::new();
+        assert_eq!(
+            registry.define(SUBNET_MASK, "Mask", OptionType::Ip),
+            Err(DefinitionError::BuiltIn(SUBNET_MASK))
+        );
+        assert_eq!(
+            registry.define(255, "End", OptionType::U8),
+            Err(DefinitionError::BuiltIn(255))
+        );
+        let unsized_first = OptionType::Record(vec![OptionType::String, OptionType::U8]);
+        assert_eq!(
+            registry.define(253, "Bad", unsized_first),
+            Err(DefinitionError::UnsizedField)
+        );
+        assert_eq!(registry.definition(253), None);
+
+        let addrs = DhcpOption::Custom(
+            254,
+            OptionValue::IpArray(vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)]),
+        );
+        assert_eq!(addrs.validate(), Ok(()));
+        assert!(DhcpOption::Custom(254, OptionValue::IpArray(vec![]))
+            .validate()
+            .is_err());
+        assert!(DhcpOption::Custom(SUBNET_MASK, OptionValue::U8(1))
+            .validate()
+            .is_err());
+        assert_eq!(OptionValue::Bytes(vec![1, 0xab]).to_string(), "01:ab");
+    }
+}
@@ -0,0 +1,957 @@
+//! Address allocation for a server: a pool of addresses, leases on them and the RFC 2131
+//! rules for offering, committing, renewing, releasing and declining them.
+//!
+//! `LeaseManager` is a `server::Handler` itself, so a server handing out addresses from a
+//! single pool needs no more than:
+//!
+//! ```no_run
+//! # use std::net::{Ipv4Addr, UdpSocket};
+//! # use std::time::Duration;
+//! use dhcp4r::lease::{LeaseManager, Pool};
+//! use dhcp4r::options::DhcpOption;
+//! use dhcp4r::server::Server;
+//!
+//! let socket = UdpSocket::bind("0.0.0.0:67").unwrap();
+//! socket.set_broadcast(true).unwrap();
+//! let pool = Pool::new(Ipv4Addr::new(192, 168, 2, 2), Ipv4Addr::new(192, 168, 2, 253));
+//! let leases = LeaseManager::new(pool, Duration::from_secs(86400)).options(vec![
+//!     DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+//!     DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 2, 1)]),
+//! ])
+//! .on_error(|e| eprintln!("{}", e));
+//! Server::serve(socket, Ipv4Addr::new(192, 168, 2, 1), Ipv4Addr::BROADCAST, leases);
+//! ```
+//!
+//! Leases are kept in memory only.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use crate::options::{DhcpOption, MessageType};
+use crate::packet::{ClientKey, HardwareAddr, Packet};
+use crate::server::{Handler, SendError, Server};
+
+/// How long an offered address is held for the client before it may be offered to others.
+pub const DEFAULT_OFFER_TIME: Duration = Duration::from_secs(120);
+
+/// How long an address a client declined (RFC 2131, section 4.3.3) is kept out of use.
+pub const DEFAULT_DECLINE_TIME: Duration = Duration::from_secs(3600);
+
+/// A range of addresses to lease, from `first` to `last` inclusive, less any excluded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pool {
+    first: u32,
+    last: u32,
+    excluded: BTreeSet<Ipv4Addr>,
+}
+
+impl Pool {
+    /// The pool is empty if `last` comes before `first`.
+    pub fn new(first: Ipv4Addr, last: Ipv4Addr) -> Pool {
+        Pool {
+            first: first.into(),
+            last: last.into(),
+            excluded: BTreeSet::new(),
+        }
+    }
+
+    /// Leaves out an address in the range, such as that of a router.
+    pub fn exclude(mut self, addr: Ipv4Addr) -> Self {
+        self.excluded.insert(addr);
+        self
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        (self.first..=self.last).contains(&u32::from(addr)) && !self.excluded.contains(&addr)
+    }
+
+    /// Number of addresses in the range, excluded ones included.
+    fn size(&self) -> u64 {
+        (self.last as u64 + 1).saturating_sub(self.first as u64)
+    }
+
+    fn nth(&self, i: u64) -> Ipv4Addr {
+        Ipv4Addr::from(self.first + i as u32)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeaseState {
+    /// Offered in a DHCPOFFER and held until the client requests it.
+    Offered,
+    /// Acknowledged with a DHCPACK.
+    Bound,
+    /// Given back by the client. The address is free, but offered to the same client first.
+    Released,
+    /// Found in use by the client. The address is out of use until the lease expires.
+    Declined,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    pub addr: Ipv4Addr,
+    pub client: ClientKey,
+    pub chaddr: HardwareAddr,
+    pub state: LeaseState,
+    pub expires: Instant,
+    /// Nonce (RFC 6704) or reconfigure key (RFC 3118) to authenticate FORCERENEW to the
+    /// client with, if it was given one.
+    pub nonce: Option<[u8; 16]>,
+}
+
+impl Lease {
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires <= now
+    }
+
+    /// Time left until the lease expires, zero if it has.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.expires.saturating_duration_since(now)
+    }
+
+    fn holds(&self, packet: &Packet) -> bool {
+        self.client == packet.client_key()
+    }
+
+    /// Whether the address may go to another client than the one holding the lease.
+    fn is_free(&self, now: Instant) -> bool {
+        self.state == LeaseState::Released || self.is_expired(now)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeaseError {
+    /// The address is not in the pool and not reserved for the client.
+    NotInPool(Ipv4Addr),
+    /// The address is leased to another client, or declined.
+    Unavailable(Ipv4Addr),
+    /// The address is reserved for another client, or the client has another reserved.
+    Reserved(Ipv4Addr),
+    /// The client holds no lease on the address.
+    NotLeased(Ipv4Addr),
+}
+
+impl fmt::Display for LeaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotInPool(addr) => write!(f, "{} is not in the pool", addr),
+            Self::Unavailable(addr) => write!(f, "{} is not available", addr),
+            Self::Reserved(addr) => write!(f, "{} is reserved", addr),
+            Self::NotLeased(addr) => write!(f, "{} is not leased to the client", addr),
+        }
+    }
+}
+
+impl std::error::Error for LeaseError {}
+
+/// What kept `LeaseManager::handle` from serving a client as it asked.
+#[derive(Debug)]
+pub enum RequestError {
+    /// No address was left to offer the client.
+    PoolExhausted(ClientKey),
+    /// The request was refused, with a DHCPNAK if it was a DHCPREQUEST, or a DHCPRELEASE
+    /// or DHCPDECLINE named an address the client holds no lease on.
+    Lease(LeaseError),
+    /// The client found the address in use by another host. It is out of use for the
+    /// decline time.
+    Declined(Ipv4Addr, ClientKey),
+    /// The reply was not sent, or sent without some options.
+    Send(SendError),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PoolExhausted(client) => write!(f, "no address left for {:?}", client),
+            Self::Lease(e) => write!(f, "refused: {}", e),
+            Self::Declined(addr, client) => {
+                write!(f, "{} declined by {:?}, possibly in use", addr, client)
+            }
+            Self::Send(e) => write!(f, "reply: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Lease(e) => Some(e),
+            Self::Send(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<LeaseError> for RequestError {
+    fn from(e: LeaseError) -> RequestError {
+        RequestError::Lease(e)
+    }
+}
+
+impl From<SendError> for RequestError {
+    fn from(e: SendError) -> RequestError {
+        RequestError::Send(e)
+    }
+}
+
+/// Leases on the addresses of a pool, and reservations of addresses for clients.
+///
+/// Clients are told apart by `Packet::client_key`. Reservations match either that or the
+/// hardware address, so a client reserved by MAC is recognised when it sends a client
+/// identifier too.
+///
+/// Free addresses are found without going through the pool: addresses are handed out in
+/// order until the pool has been gone through once, then those whose lease was forgotten,
+/// and then the address that has been free the longest. Addresses are reused as late as
+/// possible that way.
+pub struct LeaseManager {
+    pool: Pool,
+    lease_time: Duration,
+    offer_time: Duration,
+    decline_time: Duration,
+    options: Vec<DhcpOption>,
+    leases: HashMap<Ipv4Addr, Lease>,
+    // Addresses of the leases of each client, and all leases by the time they expire.
+    by_client: HashMap<ClientKey, Vec<Ipv4Addr>>,
+    by_expiry: BTreeSet<(Instant, Ipv4Addr)>,
+    reservations: HashMap<Ipv4Addr, ClientKey>,
+    reserved: HashMap<ClientKey, Ipv4Addr>,
+    // Pool addresses from this index on have not been handed out yet.
+    fresh: u64,
+    // Pool addresses below `fresh` with neither a lease nor a reservation.
+    returned: BTreeSet<Ipv4Addr>,
+    on_error: Box<dyn FnMut(RequestError) + Send>,
+}
+
+impl LeaseManager {
+    pub fn new(pool: Pool, lease_time: Duration) -> LeaseManager {
+        LeaseManager {
+            pool,
+            lease_time,
+            offer_time: DEFAULT_OFFER_TIME,
+            decline_time: DEFAULT_DECLINE_TIME,
+            options: Vec::new(),
+            leases: HashMap::new(),
+            by_client: HashMap::new(),
+            by_expiry: BTreeSet::new(),
+            reservations: HashMap::new(),
+            reserved: HashMap::new(),
+            fresh: 0,
+            returned: BTreeSet::new(),
+            on_error: Box::new(|_| {}),
+        }
+    }
+
+    pub fn offer_time(mut self, offer_time: Duration) -> Self {
+        self.offer_time = offer_time;
+        self
+    }
+
+    pub fn decline_time(mut self, decline_time: Duration) -> Self {
+        self.decline_time = decline_time;
+        self
+    }
+
+    /// Options for the handler to send in every DHCPOFFER and DHCPACK, such as the subnet
+    /// mask and routers. The lease time is added by the handler.
+    pub fn options(mut self, options: Vec<DhcpOption>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Hook for the errors of requests handled as a `server::Handler`, which are dropped
+    /// otherwise. Call `handle` instead to get them back directly.
+    pub fn on_error(mut self, on_error: impl FnMut(RequestError) + Send + 'static) -> Self {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    /// Gives `addr` to `client` only, whether or not it is in the pool. Replaces any
+    /// earlier reservation of `addr`, and any earlier reservation for `client`.
+    pub fn reserve(&mut self, client: ClientKey, addr: Ipv4Addr) {
+        if let Some(&old) = self.reserved.get(&client) {
+            self.unreserve(old);
+        }
+        self.unreserve(addr);
+        self.returned.remove(&addr);
+        self.reserved.insert(client.clone(), addr);
+        self.reservations.insert(addr, client);
+    }
+
+    pub fn unreserve(&mut self, addr: Ipv4Addr) -> Option<ClientKey> {
+        let client = self.reservations.remove(&addr)?;
+        self.reserved.remove(&client);
+        if self.pool.contains(addr) && !self.leases.contains_key(&addr) {
+            self.returned.insert(addr);
+        }
+        Some(client)
+    }
+
+    /// The address reserved for the client that sent `packet`: by its client identifier if
+    /// there is one, and otherwise by its hardware address.
+    pub fn reservation(&self, packet: &Packet) -> Option<Ipv4Addr> {
+        self.reserved
+            .get(&packet.client_key())
+            .or_else(|| self.reserved.get(&ClientKey::HardwareAddr(packet.chaddr)))
+            .copied()
+    }
+
+    pub fn lease(&self, addr: Ipv4Addr) -> Option<&Lease> {
+        self.leases.get(&addr)
+    }
+
+    pub fn leases(&self) -> impl Iterator<Item = &Lease> {
+        self.leases.values()
+    }
+
+    /// The lease of the client that sent `packet`, other than a declined one.
+    pub fn client_lease(&self, packet: &Packet) -> Option<&Lease> {
+        self.by_client
+            .get(&packet.client_key())?
+            .iter()
+            .map(|addr| &self.leases[addr])
+            .find(|l| l.state != LeaseState::Declined)
+    }
+
+    /// Whether `addr` may be leased to the client that sent `packet`.
+    pub fn check_available(
+        &self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        now: Instant,
+    ) -> Result<(), LeaseError> {
+        self.available(packet, addr, self.reservation(packet), now)
+    }
+
+    /// `check_available`, with the client's reservation already looked up.
+    fn available(
+        &self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        reserved: Option<Ipv4Addr>,
+        now: Instant,
+    ) -> Result<(), LeaseError> {
+        match reserved {
+            Some(reserved) if reserved != addr => return Err(LeaseError::Reserved(addr)),
+            Some(_) => {}
+            None if self.reservations.contains_key(&addr) => {
+                return Err(LeaseError::Reserved(addr))
+            }
+            None if !self.pool.contains(addr) => return Err(LeaseError::NotInPool(addr)),
+            None => {}
+        }
+        match self.leases.get(&addr) {
+            Some(l) if l.state == LeaseState::Declined && !l.is_expired(now) => {
+                Err(LeaseError::Unavailable(addr))
+            }
+            Some(l) if !l.holds(packet) && !l.is_free(now) => Err(LeaseError::Unavailable(addr)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Picks an address for a DHCPDISCOVER (RFC 2131, section 4.3.1) and holds it for the
+    /// client: its reservation, its current or last address, the address it asked for, or
+    /// else a free one. Returns `None` if the pool is exhausted.
+    pub fn offer(&mut self, packet: &Packet, now: Instant) -> Option<Ipv4Addr> {
+        let addr = self.choose(packet, now)?;
+        let bound = match self.leases.get(&addr) {
+            Some(l) => l.holds(packet) && l.state == LeaseState::Bound && !l.is_expired(now),
+            None => false,
+        };
+        if !bound {
+            self.withdraw_offer(packet);
+            let lease = self.new_lease(packet, addr, LeaseState::Offered, now + self.offer_time);
+            self.insert_lease(lease);
+        }
+        Some(addr)
+    }
+
+    fn choose(&mut self, packet: &Packet, now: Instant) -> Option<Ipv4Addr> {
+        let reserved = self.reservation(packet);
+        if let Some(addr) = reserved {
+            return self
+                .available(packet, addr, reserved, now)
+                .ok()
+                .map(|_| addr);
+        }
+        let previous = self.client_lease(packet).map(|l| l.addr);
+        for addr in previous.into_iter().chain(packet.requested_ip()) {
+            if self.available(packet, addr, None, now).is_ok() {
+                return Some(addr);
+            }
+        }
+        // The cursor only moves past an address once it is leased, reserved or excluded.
+        while self.fresh < self.pool.size() {
+            let addr = self.pool.nth(self.fresh);
+            if self.pool.contains(addr)
+                && !self.leases.contains_key(&addr)
+                && !self.reservations.contains_key(&addr)
+            {
+                return Some(addr);
+            }
+            self.fresh += 1;
+        }
+        if let Some(&addr) = self.returned.iter().next() {
+            return Some(addr);
+        }
+        // Only a reservation keeps a free address from going to any client.
+        self.by_expiry
+            .iter()
+            .take_while(|&&(expires, _)| expires <= now)
+            .map(|&(_, addr)| addr)
+            .find(|&addr| self.available(packet, addr, None, now).is_ok())
+    }
+
+    /// Forgets the offer made to the client, which chose another server.
+    pub fn withdraw_offer(&mut self, packet: &Packet) {
+        for addr in self.client_addrs(packet) {
+            if self.leases[&addr].state == LeaseState::Offered {
+                self.remove_lease(addr);
+            }
+        }
+    }
+
+    /// Binds `addr` to the client for a DHCPREQUEST in SELECTING state, or a DHCPDISCOVER
+    /// with Rapid Commit. Any other address the client held is freed.
+    pub fn commit(
+        &mut self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        now: Instant,
+    ) -> Result<&Lease, LeaseError> {
+        self.check_available(packet, addr, now)?;
+        let nonce = match self.leases.get(&addr) {
+            Some(l) if l.holds(packet) => l.nonce,
+            _ => None,
+        };
+        for other in self.client_addrs(packet) {
+            if other != addr && self.leases[&other].state != LeaseState::Declined {
+                self.remove_lease(other);
+            }
+        }
+        let mut lease = self.new_lease(packet, addr, LeaseState::Bound, now + self.lease_time);
+        lease.nonce = nonce;
+        self.insert_lease(lease);
+        Ok(&self.leases[&addr])
+    }
+
+    /// Extends the lease the client holds on `addr`, for a DHCPREQUEST in INIT-REBOOT,
+    /// RENEWING or REBINDING state. An expired lease is renewed too, as no other client
+    /// has been given the address since.
+    pub fn renew(
+        &mut self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        now: Instant,
+    ) -> Result<&Lease, LeaseError> {
+        match self.reservation(packet) {
+            Some(reserved) if reserved != addr => return Err(LeaseError::Reserved(addr)),
+            _ => {}
+        }
+        match self.leases.get(&addr) {
+            Some(l) if l.holds(packet) && l.state == LeaseState::Bound => {}
+            _ => return Err(LeaseError::NotLeased(addr)),
+        }
+        self.set_expiry(addr, now + self.lease_time);
+        Ok(&self.leases[&addr])
+    }
+
+    /// Frees `addr` on a DHCPRELEASE. The client is offered it again if it comes back
+    /// before the address goes to another client.
+    pub fn release(
+        &mut self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        now: Instant,
+    ) -> Result<(), LeaseError> {
+        match self.leases.get_mut(&addr) {
+            Some(l) if l.holds(packet) && l.state == LeaseState::Bound => {
+                l.state = LeaseState::Released;
+                l.nonce = None;
+            }
+            _ => return Err(LeaseError::NotLeased(addr)),
+        }
+        self.set_expiry(addr, now);
+        Ok(())
+    }
+
+    /// Takes `addr` out of use for the decline time on a DHCPDECLINE, as the client found
+    /// it in use by another host.
+    pub fn decline(
+        &mut self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        now: Instant,
+    ) -> Result<(), LeaseError> {
+        match self.leases.get_mut(&addr) {
+            Some(l) if l.holds(packet) && l.state != LeaseState::Declined => {
+                l.state = LeaseState::Declined;
+                l.nonce = None;
+            }
+            _ => return Err(LeaseError::NotLeased(addr)),
+        }
+        self.set_expiry(addr, now + self.decline_time);
+        Ok(())
+    }
+
+    /// Forgets expired leases, returning them. Addresses of expired leases can be given to
+    /// other clients without this; it only keeps the table from growing.
+    pub fn expire(&mut self, now: Instant) -> Vec<Lease> {
+        let expired: Vec<Ipv4Addr> = self
+            .by_expiry
+            .iter()
+            .take_while(|&&(expires, _)| expires <= now)
+            .map(|&(_, addr)| addr)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|addr| self.remove_lease(addr))
+            .collect()
+    }
+
+    /// Sends FORCERENEW to the client bound to `addr`, if it was given a nonce or reconfigure
+    /// key to authenticate it with.
+    pub fn forcerenew(&self, server: &Server, addr: Ipv4Addr) -> std::io::Result<usize> {
+        match self.leases.get(&addr) {
+            Some(Lease {
+                state: LeaseState::Bound,
+                chaddr,
+                nonce: Some(nonce),
+                ..
+            }) => server.forcerenew(addr, *chaddr, nonce),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("no authenticated lease on {}", addr),
+            )),
+        }
+    }
+
+    /// Addresses of the leases held by the client that sent `packet`.
+    fn client_addrs(&self, packet: &Packet) -> Vec<Ipv4Addr> {
+        match self.by_client.get(&packet.client_key()) {
+            Some(addrs) => addrs.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Puts `lease` in place of any other on its address.
+    fn insert_lease(&mut self, lease: Lease) {
+        self.remove_lease(lease.addr);
+        self.returned.remove(&lease.addr);
+        self.by_expiry.insert((lease.expires, lease.addr));
+        self.by_client
+            .entry(lease.client.clone())
+            .or_default()
+            .push(lease.addr);
+        self.leases.insert(lease.addr, lease);
+    }
+
+    fn remove_lease(&mut self, addr: Ipv4Addr) -> Option<Lease> {
+        let lease = self.leases.remove(&addr)?;
+        self.by_expiry.remove(&(lease.expires, addr));
+        if let Some(addrs) = self.by_client.get_mut(&lease.client) {
+            addrs.retain(|&a| a != addr);
+            if addrs.is_empty() {
+                self.by_client.remove(&lease.client);
+            }
+        }
+        if self.pool.contains(addr) && !self.reservations.contains_key(&addr) {
+            self.returned.insert(addr);
+        }
+        Some(lease)
+    }
+
+    fn set_expiry(&mut self, addr: Ipv4Addr, expires: Instant) {
+        if let Some(l) = self.leases.get_mut(&addr) {
+            self.by_expiry.remove(&(l.expires, addr));
+            l.expires = expires;
+            self.by_expiry.insert((expires, addr));
+        }
+    }
+
+    fn new_lease(
+        &self,
+        packet: &Packet,
+        addr: Ipv4Addr,
+        state: LeaseState,
+        expires: Instant,
+    ) -> Lease {
+        Lease {
+            addr,
+            client: packet.client_key(),
+            chaddr: packet.chaddr,
+            state,
+            expires,
+            nonce: None,
+        }
+    }
+
+    fn reply_options(&self) -> Vec<DhcpOption> {
+        let mut opts = self.options.clone();
+        // 0xffffffff would mean infinity.
+        let secs = self.lease_time.as_secs().min(u32::MAX as u64 - 1) as u32;
+        opts.push(DhcpOption::IpAddressLeaseTime(secs));
+        opts
+    }
+
+    /// Acknowledges a lease just committed or renewed. A client that can authenticate
+    /// FORCERENEW gets a new nonce or key with every DHCPACK, as it may have lost the last one.
+    fn ack(&mut self, server: &Server, packet: Packet, addr: Ipv4Addr) -> Result<(), RequestError> {
+        let mut opts = self.reply_options();
+        let key = server
+            .forcerenew_nonce(&packet)
+            .or_else(|| server.reconfigure_key(&packet));
+        if let Some((nonce, option)) = key {
+            if let Some(lease) = self.leases.get_mut(&addr) {
+                lease.nonce = Some(nonce);
+                opts.push(option);
+            }
+        }
+        server.reply(MessageType::Ack, opts, addr, packet)?;
+        Ok(())
+    }
+
+    /// Refuses a request with a DHCPNAK, returning why.
+    fn nak(&self, server: &Server, packet: Packet, error: LeaseError) -> Result<(), RequestError> {
+        server.reply(
+            MessageType::Nak,
+            vec![DhcpOption::Message(error.to_string())],
+            Ipv4Addr::UNSPECIFIED,
+            packet,
+        )?;
+        Err(RequestError::Lease(error))
+    }
+
+    fn handle_request_message(
+        &mut self,
+        server: &Server,
+        packet: Packet,
+        now: Instant,
+    ) -> Result<(), RequestError> {
+        match (packet.server_identifier(), packet.requested_ip()) {
+            // SELECTING: the client chose an offer, maybe another server's.
+            (Some(_), _) if !server.for_this_server(&packet) => {
+                self.withdraw_offer(&packet);
+                Ok(())
+            }
+            (Some(_), Some(addr)) => match self.commit(&packet, addr, now) {
+                Ok(_) => self.ack(server, packet, addr),
+                Err(e) => self.nak(server, packet, e),
+            },
+            (Some(_), None) => Ok(()),
+            // INIT-REBOOT: stay silent about clients we have no record of (RFC 2131, 4.3.2).
+            (None, Some(addr)) => {
+                if self.client_lease(&packet).is_none() && self.reservation(&packet).is_none() {
+                    if !self.pool.contains(addr) {
+                        return self.nak(server, packet, LeaseError::NotInPool(addr));
+                    }
+                    return Ok(());
+                }
+                match self.renew(&packet, addr, now) {
+                    Ok(_) => self.ack(server, packet, addr),
+                    Err(e) => self.nak(server, packet, e),
+                }
+            }
+            // RENEWING or REBINDING.
+            (None, None) => {
+                let addr = packet.ciaddr;
+                match self.renew(&packet, addr, now) {
+                    Ok(_) => self.ack(server, packet, addr),
+                    Err(e) => self.nak(server, packet, e),
+                }
+            }
+        }
+    }
+
+    /// Answers DHCPDISCOVER, DHCPREQUEST and DHCPINFORM, and records DHCPRELEASE and
+    /// DHCPDECLINE. BOOTP and other messages are ignored.
+    pub fn handle(&mut self, server: &Server, in_packet: Packet) -> Result<(), RequestError> {
+        let now = Instant::now();
+        match in_packet.message_type() {
+            Ok(MessageType::Discover) => {
+                // Server::reply leaves the address out of the offer.
+                if server.ipv6_only_wait(&in_packet).is_some() {
+                    let opts = self.options.clone();
+                    server.reply(MessageType::Offer, opts, Ipv4Addr::UNSPECIFIED, in_packet)?;
+                    return Ok(());
+                }
+                let addr = match self.offer(&in_packet, now) {
+                    Some(addr) => addr,
+                    None => return Err(RequestError::PoolExhausted(in_packet.client_key())),
+                };
+                if server.rapid_commit(&in_packet) {
+                    self.commit(&in_packet, addr, now)?;
+                    self.ack(server, in_packet, addr)
+                } else {
+                    let opts = self.reply_options();
+                    server.reply(MessageType::Offer, opts, addr, in_packet)?;
+                    Ok(())
+                }
+            }
+            Ok(MessageType::Request) => self.handle_request_message(server, in_packet, now),
+            Ok(MessageType::Release) if server.for_this_server(&in_packet) => {
+                Ok(self.release(&in_packet, in_packet.ciaddr, now)?)
+            }
+            Ok(MessageType::Decline) if server.for_this_server(&in_packet) => {
+                match in_packet.requested_ip() {
+                    Some(addr) => {
+                        self.decline(&in_packet, addr, now)?;
+                        Err(RequestError::Declined(addr, in_packet.client_key()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            // No lease time for a client that configured its address itself (RFC 2131, 3.4).
+            Ok(MessageType::Inform) => {
+                let opts = self.options.clone();
+                server.reply(MessageType::Ack, opts, Ipv4Addr::UNSPECIFIED, in_packet)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Handler for LeaseManager {
+    /// Runs `LeaseManager::handle`, passing any error to the hook set with `on_error`.
+    fn handle_request(&mut self, server: &Server, in_packet: Packet) {
+        if let Err(e) = self.handle(server, in_packet) {
+            (self.on_error)(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::ClientIdentifier;
+    use crate::server::ServerConfig;
+    use std::sync::{Arc, Mutex};
+
+    const LEASE_TIME: Duration = Duration::from_secs(3600);
+
+    fn pool() -> Pool {
+        Pool::new(Ipv4Addr::new(10, 0, 0, 10), Ipv4Addr::new(10, 0, 0, 12))
+    }
+
+    fn client(n: u8) -> Packet {
+        Packet::discover(HardwareAddr::ethernet([0, 0, 0, 0, 0, n]))
+    }
+
+    fn addr(n: u8) -> Ipv4Addr {
+        Ipv4Addr::new(10, 0, 0, n)
+    }
+
+    #[test]
+    fn pool_contains() {
+        let pool = pool().exclude(addr(11));
+        assert!(pool.contains(addr(10)));
+        assert!(!pool.contains(addr(11)));
+        assert!(pool.contains(addr(12)));
+        assert!(!pool.contains(addr(13)));
+        assert_eq!(pool.size(), 3);
+        assert_eq!(Pool::new(addr(2), addr(1)).size(), 0);
+        assert_eq!(
+            Pool::new(Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST).size(),
+            1 << 32
+        );
+    }
+
+    #[test]
+    fn offer_commit_renew_release() {
+        let now = Instant::now();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME);
+        let (a, b) = (client(1), client(2));
+
+        assert_eq!(m.offer(&a, now), Some(addr(10)));
+        // The offer is held for the client, and offered again to it.
+        assert_eq!(m.offer(&b, now), Some(addr(11)));
+        assert_eq!(m.offer(&a, now), Some(addr(10)));
+        assert_eq!(m.lease(addr(10)).unwrap().state, LeaseState::Offered);
+        assert_eq!(
+            m.commit(&b, addr(10), now),
+            Err(LeaseError::Unavailable(addr(10)))
+        );
+
+        let lease = m.commit(&a, addr(10), now).unwrap();
+        assert_eq!(lease.state, LeaseState::Bound);
+        assert_eq!(lease.remaining(now), LEASE_TIME);
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            m.renew(&a, addr(10), later).unwrap().expires,
+            later + LEASE_TIME
+        );
+        assert_eq!(
+            m.renew(&b, addr(10), later),
+            Err(LeaseError::NotLeased(addr(10)))
+        );
+        assert_eq!(
+            m.commit(&a, addr(20), now),
+            Err(LeaseError::NotInPool(addr(20)))
+        );
+
+        // Released addresses are free, but go back to the same client first.
+        assert_eq!(
+            m.release(&b, addr(10), later),
+            Err(LeaseError::NotLeased(addr(10)))
+        );
+        m.release(&a, addr(10), later).unwrap();
+        assert!(m.check_available(&b, addr(10), later).is_ok());
+        assert_eq!(m.offer(&a, later), Some(addr(10)));
+
+        // A client holds one address at a time.
+        m.commit(&a, addr(12), later).unwrap();
+        assert!(m.lease(addr(10)).is_none());
+        assert_eq!(m.client_lease(&a).unwrap().addr, addr(12));
+    }
+
+    #[test]
+    fn exhaustion_and_expiry() {
+        let now = Instant::now();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME);
+        for n in 1..=3 {
+            let addr = m.offer(&client(n), now).unwrap();
+            m.commit(&client(n), addr, now).unwrap();
+        }
+        assert_eq!(m.offer(&client(4), now), None);
+
+        // An expired lease is renewed if no other client took the address.
+        let after = now + LEASE_TIME;
+        assert!(m.renew(&client(1), addr(10), after).is_ok());
+        // Offers to a client that chose another server are withdrawn.
+        assert_eq!(m.offer(&client(4), after), Some(addr(11)));
+        m.withdraw_offer(&client(4));
+        assert_eq!(m.expire(after)[0].addr, addr(12));
+        assert_eq!(m.leases().count(), 1);
+        assert_eq!(m.client_lease(&client(1)).unwrap().addr, addr(10));
+    }
+
+    #[test]
+    fn freed_addresses_are_reused_last() {
+        let now = Instant::now();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME);
+        let later = now + Duration::from_secs(60);
+        for n in 1..=3 {
+            let addr = m.offer(&client(n), now).unwrap();
+            m.commit(&client(n), addr, now).unwrap();
+        }
+        m.release(&client(3), addr(12), later).unwrap();
+        m.release(&client(2), addr(11), now).unwrap();
+        // The address released first is offered first.
+        assert_eq!(m.offer(&client(4), later), Some(addr(11)));
+        assert_eq!(m.offer(&client(5), later), Some(addr(12)));
+        // Forgotten offers are handed out again before the pool is searched.
+        m.withdraw_offer(&client(4));
+        m.reserve(ClientKey::HardwareAddr(client(9).chaddr), addr(11));
+        assert_eq!(m.offer(&client(6), later), None);
+        m.unreserve(addr(11));
+        assert_eq!(m.offer(&client(6), later), Some(addr(11)));
+    }
+
+    #[test]
+    fn declined_addresses_are_quarantined() {
+        let now = Instant::now();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME).decline_time(Duration::from_secs(60));
+        let a = client(1);
+        let offered = m.offer(&a, now).unwrap();
+        m.commit(&a, offered, now).unwrap();
+        m.decline(&a, offered, now).unwrap();
+        assert_eq!(
+            m.check_available(&a, offered, now),
+            Err(LeaseError::Unavailable(offered))
+        );
+        assert!(m.client_lease(&a).is_none());
+        assert_ne!(m.offer(&a, now), Some(offered));
+        let later = now + Duration::from_secs(60);
+        assert!(m.check_available(&client(2), offered, later).is_ok());
+    }
+
+    #[test]
+    fn reservations() {
+        let now = Instant::now();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME);
+        let (a, b) = (client(1), client(2));
+        // Reservations may lie outside the pool, and match the hardware address.
+        m.reserve(ClientKey::HardwareAddr(a.chaddr), addr(50));
+        m.reserve(
+            ClientKey::HardwareAddr(HardwareAddr::ethernet([9; 6])),
+            addr(10),
+        );
+        let mut with_id = a.clone();
+        with_id.set_client_id(ClientIdentifier::Opaque(b"host".to_vec()));
+        assert_eq!(m.reservation(&with_id), Some(addr(50)));
+
+        assert_eq!(m.offer(&with_id, now), Some(addr(50)));
+        assert_eq!(m.offer(&b, now), Some(addr(11)));
+        assert_eq!(
+            m.commit(&b, addr(50), now),
+            Err(LeaseError::Reserved(addr(50)))
+        );
+        assert_eq!(
+            m.commit(&a, addr(12), now),
+            Err(LeaseError::Reserved(addr(12)))
+        );
+        assert!(m.commit(&with_id, addr(50), now).is_ok());
+        assert_eq!(
+            m.unreserve(addr(50)),
+            Some(ClientKey::HardwareAddr(a.chaddr))
+        );
+
+        // A client identifier reservation wins over one for the hardware address.
+        m.reserve(ClientKey::HardwareAddr(a.chaddr), addr(51));
+        m.reserve(with_id.client_key(), addr(52));
+        assert_eq!(m.reservation(&with_id), Some(addr(52)));
+        assert_eq!(
+            m.check_available(&with_id, addr(51), now),
+            Err(LeaseError::Reserved(addr(51)))
+        );
+        assert_eq!(m.reservation(&a), Some(addr(51)));
+        // Reserving another address for a client moves its reservation.
+        m.reserve(with_id.client_key(), addr(53));
+        assert_eq!(m.reservation(&with_id), Some(addr(53)));
+        assert_eq!(m.unreserve(addr(52)), None);
+    }
+
+    #[test]
+    fn handler_reports_errors() {
+        let server = Server::for_tests(addr(1), ServerConfig::default());
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let reported = errors.clone();
+        let mut m = LeaseManager::new(pool(), LEASE_TIME)
+            .on_error(move |e| reported.lock().unwrap().push(e.to_string()));
+        for n in 1..=3 {
+            assert!(m.handle(&server, client(n)).is_ok());
+        }
+        match m.handle(&server, client(4)) {
+            Err(RequestError::PoolExhausted(key)) => assert_eq!(key, client(4).client_key()),
+            other => panic!("{:?}", other),
+        }
+
+        let mut release = client(4);
+        release.set_option(DhcpOption::DhcpMessageType(MessageType::Release));
+        release.set_server_identifier(addr(1));
+        release.ciaddr = addr(10);
+        match m.handle(&server, release) {
+            Err(RequestError::Lease(LeaseError::NotLeased(a))) => assert_eq!(a, addr(10)),
+            other => panic!("{:?}", other),
+        }
+
+        let mut decline = client(1);
+        decline.set_option(DhcpOption::DhcpMessageType(MessageType::Decline));
+        decline.set_server_identifier(addr(1));
+        decline.set_requested_ip(addr(10));
+        match m.handle(&server, decline) {
+            Err(RequestError::Declined(a, key)) => {
+                assert_eq!(a, addr(10));
+                assert_eq!(key, client(1).client_key());
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // As a Handler, errors go to the hook.
+        m.handle_request(&server, client(4));
+        assert_eq!(errors.lock().unwrap().len(), 1);
+    }
+}
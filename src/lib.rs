@@ -1,6 +1,12 @@
+pub mod auth;
+pub mod builder;
+pub mod custom;
+pub mod lease;
 pub mod options;
 pub mod packet;
 pub mod server;
+pub mod text;
+pub mod vendor;
 
 #[cfg(test)]
 mod tests {
@@ -1,13 +1,382 @@
 ///use num_traits::FromPrimitive;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 use std::net::Ipv4Addr;
 
+use crate::custom::OptionValue;
+use crate::packet::DecodeErrorKind;
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct RawDhcpOption {
     pub code: u8,
     pub data: Vec<u8>,
 }
 
-#[derive(PartialEq, Debug)]
+/// An IPv4 network: an address with all bits past the prefix length clear.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Ipv4Net {
+    addr: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Ipv4Net {
+    /// Returns `None` if `prefix_len` is over 32 or `addr` has host bits set.
+    pub fn new(addr: Ipv4Addr, prefix_len: u8) -> Option<Ipv4Net> {
+        if prefix_len > 32 {
+            return None;
+        }
+        let net = Ipv4Net { addr, prefix_len };
+        if u32::from(addr) & !u32::from(net.netmask()) != 0 {
+            return None;
+        }
+        Some(net)
+    }
+
+    pub fn addr(&self) -> Ipv4Addr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        match self.prefix_len {
+            0 => Ipv4Addr::UNSPECIFIED,
+            len => Ipv4Addr::from(u32::MAX << (32 - len)),
+        }
+    }
+
+    /// Number of leading octets of the address covered by the prefix.
+    pub fn significant_octets(&self) -> usize {
+        (self.prefix_len as usize).div_ceil(8)
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & u32::from(self.netmask()) == u32::from(self.addr)
+    }
+}
+
+impl fmt::Display for Ipv4Net {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Sub-option of Relay Agent Information (option 82), added by a relay agent to the
+/// requests it forwards.
+#[derive(PartialEq, Clone, Debug)]
+pub enum RelayAgentSubOption {
+    /// Identifies the circuit the request came in on (RFC 3046).
+    CircuitId(Vec<u8>),
+    /// Identifies the remote host end of the circuit (RFC 3046).
+    RemoteId(Vec<u8>),
+    /// Subnet to allocate from, in place of the one giaddr is on (RFC 3527).
+    LinkSelection(Ipv4Addr),
+    /// Subscriber assigned by the provider (RFC 3993).
+    SubscriberId(String),
+    /// Whether the relay received the request unicast (RFC 5010).
+    RelayAgentFlags(u8),
+    /// Address to use as Server Identifier in place of the server's own (RFC 5107).
+    ServerIdentifierOverride(Ipv4Addr),
+    /// The relay listens on a port other than 67 (RFC 8357).
+    RelaySourcePort,
+    Unrecognized(RawDhcpOption),
+}
+
+impl RelayAgentSubOption {
+    pub fn to_raw(&self) -> RawDhcpOption {
+        let data = match self {
+            Self::CircuitId(x) | Self::RemoteId(x) => x.clone(),
+            Self::LinkSelection(x) | Self::ServerIdentifierOverride(x) => x.octets().to_vec(),
+            Self::SubscriberId(x) => x.as_bytes().to_vec(),
+            Self::RelayAgentFlags(x) => vec![*x],
+            Self::RelaySourcePort => vec![],
+            Self::Unrecognized(raw) => return raw.clone(),
+        };
+        RawDhcpOption {
+            code: self.code(),
+            data,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::CircuitId(_) => AGENT_CIRCUIT_ID,
+            Self::RemoteId(_) => AGENT_REMOTE_ID,
+            Self::LinkSelection(_) => LINK_SELECTION,
+            Self::SubscriberId(_) => SUBSCRIBER_ID,
+            Self::RelayAgentFlags(_) => RELAY_AGENT_FLAGS,
+            Self::ServerIdentifierOverride(_) => SERVER_IDENTIFIER_OVERRIDE,
+            Self::RelaySourcePort => RELAY_SOURCE_PORT,
+            Self::Unrecognized(x) => x.code,
+        }
+    }
+}
+
+/// Client Identifier (option 61). The first octet tells how the rest is to be read.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum ClientIdentifier {
+    /// Hardware type and address, as in `htype` and `chaddr` (RFC 2132).
+    HardwareAddr { htype: u8, addr: Vec<u8> },
+    /// Interface and DHCP Unique Identifier, shared with DHCPv6 (RFC 4361).
+    Duid { iaid: u32, duid: Duid },
+    /// Any other identifier, such as a name (type 0).
+    Opaque(Vec<u8>),
+}
+
+impl ClientIdentifier {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::HardwareAddr { htype, addr } => [&[*htype], &addr[..]].concat(),
+            Self::Duid { iaid, duid } => {
+                let mut v = vec![CLIENT_IDENTIFIER_DUID];
+                v.extend(iaid.to_be_bytes().iter());
+                v.extend(duid.to_bytes());
+                v
+            }
+            Self::Opaque(id) => [&[CLIENT_IDENTIFIER_OPAQUE], &id[..]].concat(),
+        }
+    }
+}
+
+/// DHCP Unique Identifier (RFC 8415, section 11).
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Duid {
+    /// Link-layer address plus the time it was generated (DUID-LLT).
+    LinkLayerTime {
+        hardware_type: u16,
+        time: u32,
+        addr: Vec<u8>,
+    },
+    /// Vendor-assigned, under the vendor's enterprise number (DUID-EN).
+    Enterprise {
+        enterprise_number: u32,
+        id: Vec<u8>,
+    },
+    /// Link-layer address (DUID-LL).
+    LinkLayer {
+        hardware_type: u16,
+        addr: Vec<u8>,
+    },
+    /// UUID (DUID-UUID, RFC 6355).
+    Uuid([u8; 16]),
+    Other {
+        duid_type: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl Duid {
+    pub fn duid_type(&self) -> u16 {
+        match self {
+            Self::LinkLayerTime { .. } => DUID_LLT,
+            Self::Enterprise { .. } => DUID_EN,
+            Self::LinkLayer { .. } => DUID_LL,
+            Self::Uuid(_) => DUID_UUID,
+            Self::Other { duid_type, .. } => *duid_type,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = self.duid_type().to_be_bytes().to_vec();
+        match self {
+            Self::LinkLayerTime {
+                hardware_type,
+                time,
+                addr,
+            } => {
+                v.extend(hardware_type.to_be_bytes().iter());
+                v.extend(time.to_be_bytes().iter());
+                v.extend(addr);
+            }
+            Self::Enterprise {
+                enterprise_number,
+                id,
+            } => {
+                v.extend(enterprise_number.to_be_bytes().iter());
+                v.extend(id);
+            }
+            Self::LinkLayer {
+                hardware_type,
+                addr,
+            } => {
+                v.extend(hardware_type.to_be_bytes().iter());
+                v.extend(addr);
+            }
+            Self::Uuid(uuid) => v.extend(uuid.iter()),
+            Self::Other { data, .. } => v.extend(data),
+        }
+        v
+    }
+}
+
+/// Client FQDN (option 81, RFC 4702): the client's name and who updates DNS for it.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ClientFqdn {
+    /// `FQDN_FLAG_*` bits.
+    pub flags: u8,
+    pub rcode1: u8,
+    pub rcode2: u8,
+    pub labels: Vec<String>,
+    /// Whether the name ends at the root, as opposed to a partial name the server
+    /// is expected to complete.
+    pub fully_qualified: bool,
+}
+
+impl ClientFqdn {
+    /// The name in wire format, fully qualified if it ends with a dot. Returns `None`
+    /// if a label is not a valid host name label (RFC 1123) or the name is too long.
+    pub fn new(name: &str) -> Option<ClientFqdn> {
+        let fully_qualified = name.ends_with('.');
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let labels: Vec<String> = match name {
+            "" => Vec::new(),
+            _ => name.split('.').map(str::to_string).collect(),
+        };
+        let fqdn = ClientFqdn {
+            flags: FQDN_FLAG_E,
+            rcode1: 0,
+            rcode2: 0,
+            labels,
+            fully_qualified,
+        };
+        if !fqdn.labels.iter().all(|l| is_host_label(l)) || fqdn.wire_name().len() > 255 {
+            return None;
+        }
+        Some(fqdn)
+    }
+
+    /// The dotted name, ending with a dot if fully qualified.
+    pub fn name(&self) -> String {
+        let mut name = self.labels.join(".");
+        if self.fully_qualified {
+            name.push('.');
+        }
+        name
+    }
+
+    /// The option a server answers this one with. `server_updates` tells whether the
+    /// server updates the A RR itself, which overrides the client if it asked otherwise.
+    pub fn reply(&self, server_updates: bool) -> ClientFqdn {
+        let mut flags = self.flags & FQDN_FLAG_E;
+        if server_updates {
+            flags |= FQDN_FLAG_S;
+        }
+        if server_updates != (self.flags & FQDN_FLAG_S != 0) {
+            flags |= FQDN_FLAG_O;
+        }
+        ClientFqdn {
+            flags,
+            // RFC 4702 section 4: servers set both to 255.
+            rcode1: 255,
+            rcode2: 255,
+            labels: self.labels.clone(),
+            fully_qualified: self.fully_qualified,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = vec![self.flags, self.rcode1, self.rcode2];
+        if self.flags & FQDN_FLAG_E != 0 {
+            v.extend(self.wire_name());
+        } else {
+            v.extend(self.name().as_bytes());
+        }
+        v
+    }
+
+    fn wire_name(&self) -> Vec<u8> {
+        let mut v = Vec::new();
+        for label in &self.labels {
+            v.push(label.len() as u8);
+            v.extend(label.as_bytes());
+        }
+        if self.fully_qualified {
+            v.push(0);
+        }
+        v
+    }
+}
+
+/// Authentication (option 90, RFC 3118). What the information holds depends on the
+/// protocol and algorithm.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Authentication {
+    pub protocol: u8,
+    pub algorithm: u8,
+    /// Replay detection method, such as `AUTH_RDM_MONOTONIC`.
+    pub rdm: u8,
+    pub replay_detection: u64,
+    pub information: Vec<u8>,
+}
+
+impl Authentication {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = vec![self.protocol, self.algorithm, self.rdm];
+        v.extend(self.replay_detection.to_be_bytes().iter());
+        v.extend(&self.information);
+        v
+    }
+}
+
+/// Writes each instance preceded by its length, as for user classes (RFC 3004).
+fn encode_instances(instances: &[Vec<u8>]) -> Vec<u8> {
+    let mut v = Vec::new();
+    for instance in instances {
+        v.push(instance.len() as u8);
+        v.extend(instance);
+    }
+    v
+}
+
+/// Writes domain names in DNS wire format, replacing a suffix already written by a
+/// pointer to it (RFC 1035, section 4.1.4), as option 119 requires. The names are
+/// expected to pass `is_domain_name`; `DhcpOption::validate` checks that they do.
+pub(crate) fn encode_domain_search(names: &[String]) -> Vec<u8> {
+    let mut v = Vec::new();
+    let mut written: HashMap<&str, usize> = HashMap::new();
+    'names: for name in names {
+        let mut suffix = name.as_str();
+        while !suffix.is_empty() {
+            if let Some(&offset) = written.get(suffix) {
+                v.extend((0xc000 | offset as u16).to_be_bytes().iter());
+                continue 'names;
+            }
+            // Pointers have 14 bits for the offset.
+            if v.len() < 0x4000 {
+                written.insert(suffix, v.len());
+            }
+            let (label, rest) = suffix.split_once('.').unwrap_or((suffix, ""));
+            v.push(label.len() as u8);
+            v.extend(label.as_bytes());
+            suffix = rest;
+        }
+        v.push(0);
+    }
+    v
+}
+
+/// A name as option 119 carries it: host name labels separated by dots, without the
+/// trailing dot, and at most 255 octets in wire format (RFC 1035, section 2.3.4).
+pub fn is_domain_name(name: &str) -> bool {
+    name.len() + 2 <= 255 && name.split('.').all(is_host_label)
+}
+
+/// A label of a host name: letters, digits and hyphens, not starting or ending
+/// with a hyphen, 1 to 63 characters long (RFC 1123, section 2.1).
+pub fn is_host_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub enum DhcpOption {
     DhcpMessageType(MessageType),
     ServerIdentifier(Ipv4Addr),
@@ -17,67 +386,251 @@ pub enum DhcpOption {
     Router(Vec<Ipv4Addr>),
     DomainNameServer(Vec<Ipv4Addr>),
     IpAddressLeaseTime(u32),
+    MaximumDhcpMessageSize(u16),
     SubnetMask(Ipv4Addr),
     Message(String),
+    TimeOffset(i32),
+    TimeServer(Vec<Ipv4Addr>),
+    NameServer(Vec<Ipv4Addr>),
+    LogServer(Vec<Ipv4Addr>),
+    CookieServer(Vec<Ipv4Addr>),
+    LprServer(Vec<Ipv4Addr>),
+    ImpressServer(Vec<Ipv4Addr>),
+    ResourceLocationServer(Vec<Ipv4Addr>),
+    BootFileSize(u16),
+    MeritDumpFile(String),
+    DomainName(String),
+    SwapServer(Ipv4Addr),
+    RootPath(String),
+    ExtensionsPath(String),
+    IpForwarding(bool),
+    NonLocalSourceRouting(bool),
+    PolicyFilter(Vec<(Ipv4Addr, Ipv4Addr)>),
+    MaximumDatagramReassemblySize(u16),
+    DefaultIpTimeToLive(u8),
+    PathMtuAgingTimeout(u32),
+    PathMtuPlateauTable(Vec<u16>),
+    InterfaceMtu(u16),
+    AllSubnetsAreLocal(bool),
+    BroadcastAddress(Ipv4Addr),
+    PerformMaskDiscovery(bool),
+    MaskSupplier(bool),
+    PerformRouterDiscovery(bool),
+    RouterSolicitationAddress(Ipv4Addr),
+    StaticRoute(Vec<(Ipv4Addr, Ipv4Addr)>),
+    TrailerEncapsulation(bool),
+    ArpCacheTimeout(u32),
+    EthernetEncapsulation(bool),
+    TcpDefaultTtl(u8),
+    TcpKeepaliveInterval(u32),
+    TcpKeepaliveGarbage(bool),
+    NetworkInformationServiceDomain(String),
+    NetworkInformationServers(Vec<Ipv4Addr>),
+    NetworkTimeProtocolServers(Vec<Ipv4Addr>),
+    VendorSpecificInformation(Vec<u8>),
+    NetbiosNameServer(Vec<Ipv4Addr>),
+    NetbiosDatagramDistributionServer(Vec<Ipv4Addr>),
+    NetbiosNodeType(u8),
+    NetbiosScope(String),
+    XWindowSystemFontServer(Vec<Ipv4Addr>),
+    XWindowSystemDisplayManager(Vec<Ipv4Addr>),
+    NetworkInformationServicePlusDomain(String),
+    NetworkInformationServicePlusServers(Vec<Ipv4Addr>),
+    MobileIpHomeAgent(Vec<Ipv4Addr>),
+    SmtpServer(Vec<Ipv4Addr>),
+    Pop3Server(Vec<Ipv4Addr>),
+    NntpServer(Vec<Ipv4Addr>),
+    WwwServer(Vec<Ipv4Addr>),
+    FingerServer(Vec<Ipv4Addr>),
+    IrcServer(Vec<Ipv4Addr>),
+    StreetTalkServer(Vec<Ipv4Addr>),
+    StreetTalkDirectoryAssistanceServer(Vec<Ipv4Addr>),
+    RelayAgentInformation(Vec<RelayAgentSubOption>),
+    Overload(u8),
+    RenewalTimeValue(u32),
+    RebindingTimeValue(u32),
+    VendorClassIdentifier(Vec<u8>),
+    ClientIdentifier(ClientIdentifier),
+    ClientFqdn(ClientFqdn),
+    /// Domain names without the trailing dot, such as "example.com".
+    DomainSearch(Vec<String>),
+    RapidCommit,
+    /// Seconds to leave IPv4 disabled for, called V6ONLY_WAIT (RFC 8925).
+    Ipv6OnlyPreferred(u32),
+    /// URI of the captive portal API (RFC 8910).
+    CaptivePortal(String),
+    /// Whether the client may configure a link-local address itself (RFC 2563).
+    AutoConfigure(bool),
+    Authentication(Authentication),
+    /// Authentication algorithms the client accepts a FORCERENEW nonce for (RFC 6704).
+    ForcerenewNonceCapable(Vec<u8>),
+    /// Vendor class data by enterprise number (RFC 3925).
+    ViVendorClass(Vec<(u32, Vec<Vec<u8>>)>),
+    /// Sub-options by enterprise number (RFC 3925).
+    ViVendorSpecificInformation(Vec<(u32, Vec<RawDhcpOption>)>),
+    TftpServerName(String),
+    BootfileName(String),
+    /// User class instances (RFC 3004). Clients such as iPXE send a single string
+    /// without the length octet, which is read as one instance.
+    UserClass(Vec<Vec<u8>>),
+    ClientArchitecture(Vec<u16>),
+    TzPosixString(String),
+    TzDatabaseString(String),
+    ClasslessStaticRoute(Vec<(Ipv4Net, Ipv4Addr)>),
+    MsClasslessStaticRoute(Vec<(Ipv4Net, Ipv4Addr)>),
+    /// An option defined at run time in a `custom::OptionRegistry`.
+    Custom(u8, OptionValue),
     Unrecognized(RawDhcpOption),
 }
 
 impl DhcpOption {
+    /// The option's code and data. A nested value too long for its length octet, such
+    /// as an option 82 sub-option over 255 octets, is not checked here: `validate` fails
+    /// for it, and `Packet::encode_with_limit` leaves such options out.
     pub fn to_raw(&self) -> RawDhcpOption {
-        match self {
-            Self::DhcpMessageType(mtype) => RawDhcpOption {
-                code: DHCP_MESSAGE_TYPE,
-                data: vec![*mtype as u8],
-            },
-            Self::ServerIdentifier(addr) => RawDhcpOption {
-                code: SERVER_IDENTIFIER,
-                data: addr.octets().to_vec(),
-            },
-            Self::ParameterRequestList(prl) => RawDhcpOption {
-                code: PARAMETER_REQUEST_LIST,
-                data: prl.clone(),
-            },
-            Self::RequestedIpAddress(addr) => RawDhcpOption {
-                code: REQUESTED_IP_ADDRESS,
-                data: addr.octets().to_vec(),
-            },
-            Self::HostName(name) => RawDhcpOption {
-                code: HOST_NAME,
-                data: name.as_bytes().to_vec(),
-            },
-            Self::Router(addrs) => RawDhcpOption {
-                code: ROUTER,
-                data: {
-                    let mut v = vec![];
-                    for a in addrs {
-                        v.extend(a.octets().iter());
-                    }
-                    v
-                },
-            },
-            Self::DomainNameServer(addrs) => RawDhcpOption {
-                code: DOMAIN_NAME_SERVER,
-                data: {
-                    let mut v = vec![];
-                    for a in addrs {
-                        v.extend(a.octets().iter());
+        let data = match self {
+            Self::DhcpMessageType(mtype) => vec![mtype.code()],
+            Self::ServerIdentifier(x)
+            | Self::RequestedIpAddress(x)
+            | Self::SubnetMask(x)
+            | Self::SwapServer(x)
+            | Self::BroadcastAddress(x)
+            | Self::RouterSolicitationAddress(x) => x.octets().to_vec(),
+            Self::Router(x)
+            | Self::DomainNameServer(x)
+            | Self::TimeServer(x)
+            | Self::NameServer(x)
+            | Self::LogServer(x)
+            | Self::CookieServer(x)
+            | Self::LprServer(x)
+            | Self::ImpressServer(x)
+            | Self::ResourceLocationServer(x)
+            | Self::NetworkInformationServers(x)
+            | Self::NetworkTimeProtocolServers(x)
+            | Self::NetbiosNameServer(x)
+            | Self::NetbiosDatagramDistributionServer(x)
+            | Self::XWindowSystemFontServer(x)
+            | Self::XWindowSystemDisplayManager(x)
+            | Self::NetworkInformationServicePlusServers(x)
+            | Self::MobileIpHomeAgent(x)
+            | Self::SmtpServer(x)
+            | Self::Pop3Server(x)
+            | Self::NntpServer(x)
+            | Self::WwwServer(x)
+            | Self::FingerServer(x)
+            | Self::IrcServer(x)
+            | Self::StreetTalkServer(x)
+            | Self::StreetTalkDirectoryAssistanceServer(x) => {
+                x.iter().flat_map(|a| a.octets()).collect()
+            }
+            Self::HostName(x)
+            | Self::Message(x)
+            | Self::MeritDumpFile(x)
+            | Self::DomainName(x)
+            | Self::RootPath(x)
+            | Self::ExtensionsPath(x)
+            | Self::NetworkInformationServiceDomain(x)
+            | Self::NetbiosScope(x)
+            | Self::NetworkInformationServicePlusDomain(x)
+            | Self::TftpServerName(x)
+            | Self::BootfileName(x)
+            | Self::TzPosixString(x)
+            | Self::TzDatabaseString(x)
+            | Self::CaptivePortal(x) => x.as_bytes().to_vec(),
+            Self::ParameterRequestList(x)
+            | Self::ForcerenewNonceCapable(x)
+            | Self::VendorSpecificInformation(x)
+            | Self::VendorClassIdentifier(x) => x.clone(),
+            Self::DefaultIpTimeToLive(x)
+            | Self::TcpDefaultTtl(x)
+            | Self::NetbiosNodeType(x)
+            | Self::Overload(x) => vec![*x],
+            Self::MaximumDhcpMessageSize(x)
+            | Self::BootFileSize(x)
+            | Self::MaximumDatagramReassemblySize(x)
+            | Self::InterfaceMtu(x) => x.to_be_bytes().to_vec(),
+            Self::IpAddressLeaseTime(x)
+            | Self::PathMtuAgingTimeout(x)
+            | Self::ArpCacheTimeout(x)
+            | Self::TcpKeepaliveInterval(x)
+            | Self::RenewalTimeValue(x)
+            | Self::RebindingTimeValue(x)
+            | Self::Ipv6OnlyPreferred(x) => x.to_be_bytes().to_vec(),
+            Self::TimeOffset(x) => x.to_be_bytes().to_vec(),
+            Self::IpForwarding(x)
+            | Self::NonLocalSourceRouting(x)
+            | Self::AllSubnetsAreLocal(x)
+            | Self::PerformMaskDiscovery(x)
+            | Self::MaskSupplier(x)
+            | Self::PerformRouterDiscovery(x)
+            | Self::TrailerEncapsulation(x)
+            | Self::EthernetEncapsulation(x)
+            | Self::TcpKeepaliveGarbage(x)
+            | Self::AutoConfigure(x) => vec![*x as u8],
+            Self::PolicyFilter(x) | Self::StaticRoute(x) => x
+                .iter()
+                .flat_map(|(a, b)| [a.octets(), b.octets()].concat())
+                .collect(),
+            Self::PathMtuPlateauTable(x) | Self::ClientArchitecture(x) => {
+                x.iter().flat_map(|v| v.to_be_bytes()).collect()
+            }
+            Self::ClasslessStaticRoute(x) | Self::MsClasslessStaticRoute(x) => {
+                let mut v = vec![];
+                for (dest, router) in x {
+                    // Only the significant octets of the destination are sent (RFC 3442).
+                    v.push(dest.prefix_len());
+                    v.extend(&dest.addr().octets()[..dest.significant_octets()]);
+                    v.extend(router.octets().iter());
+                }
+                v
+            }
+            Self::RelayAgentInformation(x) => {
+                let mut v = vec![];
+                for sub in x {
+                    let raw = sub.to_raw();
+                    v.push(raw.code);
+                    v.push(raw.data.len() as u8);
+                    v.extend(raw.data);
+                }
+                v
+            }
+            Self::ClientIdentifier(x) => x.to_bytes(),
+            Self::ClientFqdn(x) => x.to_bytes(),
+            Self::Authentication(x) => x.to_bytes(),
+            Self::DomainSearch(x) => encode_domain_search(x),
+            Self::RapidCommit => vec![],
+            Self::UserClass(x) => encode_instances(x),
+            Self::ViVendorClass(x) => {
+                let mut v = vec![];
+                for (enterprise, classes) in x {
+                    let data = encode_instances(classes);
+                    v.extend(enterprise.to_be_bytes().iter());
+                    v.push(data.len() as u8);
+                    v.extend(data);
+                }
+                v
+            }
+            Self::ViVendorSpecificInformation(x) => {
+                let mut v = vec![];
+                for (enterprise, sub_options) in x {
+                    let mut data = vec![];
+                    for sub in sub_options {
+                        data.push(sub.code);
+                        data.push(sub.data.len() as u8);
+                        data.extend(&sub.data);
                     }
-                    v
-                },
-            },
-            Self::IpAddressLeaseTime(secs) => RawDhcpOption {
-                code: IP_ADDRESS_LEASE_TIME,
-                data: secs.to_be_bytes().to_vec(),
-            },
-            Self::SubnetMask(mask) => RawDhcpOption {
-                code: SUBNET_MASK,
-                data: mask.octets().to_vec(),
-            },
-            Self::Message(msg) => RawDhcpOption {
-                code: MESSAGE,
-                data: msg.as_bytes().to_vec(),
-            },
-            Self::Unrecognized(raw) => raw.clone(),
+                    v.extend(enterprise.to_be_bytes().iter());
+                    v.push(data.len() as u8);
+                    v.extend(data);
+                }
+                v
+            }
+            Self::Custom(_, value) => value.to_bytes(),
+            Self::Unrecognized(raw) => return raw.clone(),
+        };
+        RawDhcpOption {
+            code: self.code(),
+            data,
         }
     }
 
@@ -91,8 +644,94 @@ impl DhcpOption {
             Self::Router(_) => ROUTER,
             Self::DomainNameServer(_) => DOMAIN_NAME_SERVER,
             Self::IpAddressLeaseTime(_) => IP_ADDRESS_LEASE_TIME,
+            Self::MaximumDhcpMessageSize(_) => MAXIMUM_DHCP_MESSAGE_SIZE,
             Self::SubnetMask(_) => SUBNET_MASK,
             Self::Message(_) => MESSAGE,
+            Self::TimeOffset(_) => TIME_OFFSET,
+            Self::TimeServer(_) => TIME_SERVER,
+            Self::NameServer(_) => NAME_SERVER,
+            Self::LogServer(_) => LOG_SERVER,
+            Self::CookieServer(_) => COOKIE_SERVER,
+            Self::LprServer(_) => LPR_SERVER,
+            Self::ImpressServer(_) => IMPRESS_SERVER,
+            Self::ResourceLocationServer(_) => RESOURCE_LOCATION_SERVER,
+            Self::BootFileSize(_) => BOOT_FILE_SIZE,
+            Self::MeritDumpFile(_) => MERIT_DUMP_FILE,
+            Self::DomainName(_) => DOMAIN_NAME,
+            Self::SwapServer(_) => SWAP_SERVER,
+            Self::RootPath(_) => ROOT_PATH,
+            Self::ExtensionsPath(_) => EXTENSIONS_PATH,
+            Self::IpForwarding(_) => IP_FORWARDING_ENABLE_DISABLE,
+            Self::NonLocalSourceRouting(_) => NON_LOCAL_SOURCE_ROUTING_ENABLE_DISABLE,
+            Self::PolicyFilter(_) => POLICY_FILTER,
+            Self::MaximumDatagramReassemblySize(_) => MAXIMUM_DATAGRAM_REASSEMBLY_SIZE,
+            Self::DefaultIpTimeToLive(_) => DEFAULT_IP_TIME_TO_LIVE,
+            Self::PathMtuAgingTimeout(_) => PATH_MTU_AGING_TIMEOUT,
+            Self::PathMtuPlateauTable(_) => PATH_MTU_PLATEAU_TABLE,
+            Self::InterfaceMtu(_) => INTERFACE_MTU,
+            Self::AllSubnetsAreLocal(_) => ALL_SUBNETS_ARE_LOCAL,
+            Self::BroadcastAddress(_) => BROADCAST_ADDRESS,
+            Self::PerformMaskDiscovery(_) => PERFORM_MASK_DISCOVERY,
+            Self::MaskSupplier(_) => MASK_SUPPLIER,
+            Self::PerformRouterDiscovery(_) => PERFORM_ROUTER_DISCOVERY,
+            Self::RouterSolicitationAddress(_) => ROUTER_SOLICITATION_ADDRESS,
+            Self::StaticRoute(_) => STATIC_ROUTE,
+            Self::TrailerEncapsulation(_) => TRAILER_ENCAPSULATION,
+            Self::ArpCacheTimeout(_) => ARP_CACHE_TIMEOUT,
+            Self::EthernetEncapsulation(_) => ETHERNET_ENCAPSULATION,
+            Self::TcpDefaultTtl(_) => TCP_DEFAULT_TTL,
+            Self::TcpKeepaliveInterval(_) => TCP_KEEPALIVE_INTERVAL,
+            Self::TcpKeepaliveGarbage(_) => TCP_KEEPALIVE_GARBAGE,
+            Self::NetworkInformationServiceDomain(_) => NETWORK_INFORMATION_SERVICE_DOMAIN,
+            Self::NetworkInformationServers(_) => NETWORK_INFORMATION_SERVERS,
+            Self::NetworkTimeProtocolServers(_) => NETWORK_TIME_PROTOCOL_SERVERS,
+            Self::VendorSpecificInformation(_) => VENDOR_SPECIFIC_INFORMATION,
+            Self::NetbiosNameServer(_) => NETBIOS_OVER_TCPIP_NAME_SERVER,
+            Self::NetbiosDatagramDistributionServer(_) => {
+                NETBIOS_OVER_TCPIP_DATAGRAM_DISTRIBUTION_SERVER
+            }
+            Self::NetbiosNodeType(_) => NETBIOS_OVER_TCPIP_NODE_TYPE,
+            Self::NetbiosScope(_) => NETBIOS_OVER_TCPIP_SCOPE,
+            Self::XWindowSystemFontServer(_) => XWINDOW_SYSTEM_FONT_SERVER,
+            Self::XWindowSystemDisplayManager(_) => XWINDOW_SYSTEM_DISPLAY_MANAGER,
+            Self::NetworkInformationServicePlusDomain(_) => NETWORK_INFORMATION_SERVICEPLUS_DOMAIN,
+            Self::NetworkInformationServicePlusServers(_) => {
+                NETWORK_INFORMATION_SERVICEPLUS_SERVERS
+            }
+            Self::MobileIpHomeAgent(_) => MOBILE_IP_HOME_AGENT,
+            Self::SmtpServer(_) => SIMPLE_MAIL_TRANSPORT_PROTOCOL,
+            Self::Pop3Server(_) => POST_OFFICE_PROTOCOL_SERVER,
+            Self::NntpServer(_) => NETWORK_NEWS_TRANSPORT_PROTOCOL,
+            Self::WwwServer(_) => DEFAULT_WORLD_WIDE_WEB_SERVER,
+            Self::FingerServer(_) => DEFAULT_FINGER_SERVER,
+            Self::IrcServer(_) => DEFAULT_INTERNET_RELAY_CHAT_SERVER,
+            Self::StreetTalkServer(_) => STREETTALK_SERVER,
+            Self::StreetTalkDirectoryAssistanceServer(_) => STREETTALK_DIRECTORY_ASSISTANCE,
+            Self::RelayAgentInformation(_) => RELAY_AGENT_INFORMATION,
+            Self::Overload(_) => OVERLOAD,
+            Self::RenewalTimeValue(_) => RENEWAL_TIME_VALUE,
+            Self::RebindingTimeValue(_) => REBINDING_TIME_VALUE,
+            Self::VendorClassIdentifier(_) => VENDOR_CLASS_IDENTIFIER,
+            Self::ClientIdentifier(_) => CLIENT_IDENTIFIER,
+            Self::ClientFqdn(_) => CLIENT_FQDN,
+            Self::DomainSearch(_) => DOMAIN_SEARCH,
+            Self::RapidCommit => RAPID_COMMIT,
+            Self::Ipv6OnlyPreferred(_) => IPV6_ONLY_PREFERRED,
+            Self::CaptivePortal(_) => CAPTIVE_PORTAL,
+            Self::AutoConfigure(_) => AUTO_CONFIGURE,
+            Self::Authentication(_) => AUTHENTICATION,
+            Self::ForcerenewNonceCapable(_) => FORCERENEW_NONCE_CAPABLE,
+            Self::ViVendorClass(_) => V_I_VENDOR_CLASS,
+            Self::ViVendorSpecificInformation(_) => V_I_VENDOR_SPECIFIC_INFORMATION,
+            Self::TftpServerName(_) => TFTP_SERVER_NAME,
+            Self::BootfileName(_) => BOOTFILE_NAME,
+            Self::UserClass(_) => USER_CLASS,
+            Self::ClientArchitecture(_) => CLIENT_ARCHITECTURE,
+            Self::TzPosixString(_) => TZ_POSIX_STRING,
+            Self::TzDatabaseString(_) => TZ_DATABASE_STRING,
+            Self::ClasslessStaticRoute(_) => CLASSLESS_ROUTE_FORMAT,
+            Self::MsClasslessStaticRoute(_) => MS_CLASSLESS_STATIC_ROUTE,
+            Self::Custom(code, _) => *code,
             Self::Unrecognized(x) => x.code,
         }
     }
@@ -172,6 +811,18 @@ pub const STREETTALK_DIRECTORY_ASSISTANCE: u8 = 76;
 
 pub const RELAY_AGENT_INFORMATION: u8 = 82;
 
+// RELAY AGENT INFORMATION SUB-OPTIONS
+pub const AGENT_CIRCUIT_ID: u8 = 1;
+pub const AGENT_REMOTE_ID: u8 = 2;
+pub const LINK_SELECTION: u8 = 5;
+pub const SUBSCRIBER_ID: u8 = 6;
+pub const RELAY_AGENT_FLAGS: u8 = 10;
+pub const SERVER_IDENTIFIER_OVERRIDE: u8 = 11;
+pub const RELAY_SOURCE_PORT: u8 = 19;
+
+/// Bit of the relay agent flags set if the relay received the request unicast.
+pub const RELAY_AGENT_FLAG_UNICAST: u8 = 0x80;
+
 // DHCP EXTENSIONS
 pub const REQUESTED_IP_ADDRESS: u8 = 50;
 pub const IP_ADDRESS_LEASE_TIME: u8 = 51;
@@ -186,19 +837,89 @@ pub const REBINDING_TIME_VALUE: u8 = 59;
 pub const VENDOR_CLASS_IDENTIFIER: u8 = 60;
 pub const CLIENT_IDENTIFIER: u8 = 61;
 
+// CLIENT IDENTIFIER TYPES
+pub const CLIENT_IDENTIFIER_OPAQUE: u8 = 0;
+pub const CLIENT_IDENTIFIER_DUID: u8 = 255;
+
+// DUID TYPES
+pub const DUID_LLT: u16 = 1;
+pub const DUID_EN: u16 = 2;
+pub const DUID_LL: u16 = 3;
+pub const DUID_UUID: u16 = 4;
+
 pub const TFTP_SERVER_NAME: u8 = 66;
 pub const BOOTFILE_NAME: u8 = 67;
 
 pub const USER_CLASS: u8 = 77;
 
+pub const CLIENT_FQDN: u8 = 81;
+
+// CLIENT FQDN FLAGS
+/// The server should update, or has updated, the A RR.
+pub const FQDN_FLAG_S: u8 = 0x01;
+/// The server has overridden the client's preference for the S bit.
+pub const FQDN_FLAG_O: u8 = 0x02;
+/// The name is in canonical DNS wire format rather than ASCII.
+pub const FQDN_FLAG_E: u8 = 0x04;
+/// The server should not, or will not, perform any DNS updates.
+pub const FQDN_FLAG_N: u8 = 0x08;
+
+pub const RAPID_COMMIT: u8 = 80;
+
+pub const AUTHENTICATION: u8 = 90;
+
+// AUTHENTICATION PROTOCOLS (RFC 3118, RFC 3315, RFC 6704)
+pub const AUTH_PROTOCOL_CONFIGURATION_TOKEN: u8 = 0;
+pub const AUTH_PROTOCOL_DELAYED: u8 = 1;
+pub const AUTH_PROTOCOL_RECONFIGURE_KEY: u8 = 3;
+/// The nonce protocol shares its value and wire format with the reconfigure key.
+pub const AUTH_PROTOCOL_FORCERENEW_NONCE: u8 = 3;
+
+pub const AUTH_ALGORITHM_HMAC_MD5: u8 = 1;
+
+/// Replay detection by a counter that only goes up.
+pub const AUTH_RDM_MONOTONIC: u8 = 0;
+
+// FORCERENEW NONCE AUTHENTICATION INFORMATION TYPES (RFC 6704)
+/// The nonce itself, handed to the client in a DHCPACK.
+pub const FORCERENEW_NONCE_VALUE: u8 = 1;
+/// HMAC-MD5 digest of a FORCERENEW, keyed with the nonce.
+pub const FORCERENEW_NONCE_HMAC_MD5: u8 = 2;
+
+// RECONFIGURE KEY AUTHENTICATION INFORMATION TYPES (RFC 3315, section 21.5.1)
+/// The reconfigure key itself, handed to the client in a DHCPACK.
+pub const RECONFIGURE_KEY_VALUE: u8 = 1;
+/// HMAC-MD5 digest of a FORCERENEW, keyed with the reconfigure key.
+pub const RECONFIGURE_KEY_HMAC_MD5: u8 = 2;
+
 pub const CLIENT_ARCHITECTURE: u8 = 93;
 
 pub const TZ_POSIX_STRING: u8 = 100;
 pub const TZ_DATABASE_STRING: u8 = 101;
 
+pub const IPV6_ONLY_PREFERRED: u8 = 108;
+
+/// Shortest V6ONLY_WAIT a client accepts, in seconds (RFC 8925).
+pub const MIN_V6ONLY_WAIT: u32 = 300;
+
+pub const CAPTIVE_PORTAL: u8 = 114;
+
+pub const AUTO_CONFIGURE: u8 = 116;
+
+pub const DOMAIN_SEARCH: u8 = 119;
+
 pub const CLASSLESS_ROUTE_FORMAT: u8 = 121;
 
-/// Returns title of DHCP Option code, if known.
+pub const V_I_VENDOR_CLASS: u8 = 124;
+pub const V_I_VENDOR_SPECIFIC_INFORMATION: u8 = 125;
+
+pub const FORCERENEW_NONCE_CAPABLE: u8 = 145;
+
+/// Pre-standard copy of option 121 that older Windows clients ask for instead.
+pub const MS_CLASSLESS_STATIC_ROUTE: u8 = 249;
+
+/// Returns title of DHCP Option code, if known. See `custom::OptionRegistry::title` for the
+/// names of custom options.
 pub fn title(code: u8) -> Option<&'static str> {
     Some(match code {
         SUBNET_MASK => "Subnet Mask",
@@ -296,13 +1017,123 @@ pub fn title(code: u8) -> Option<&'static str> {
         BOOTFILE_NAME => "Bootfile name",
 
         USER_CLASS => "User Class",
+        CLIENT_FQDN => "Client FQDN",
+        RAPID_COMMIT => "Rapid Commit",
+        AUTHENTICATION => "Authentication",
 
         CLIENT_ARCHITECTURE => "Client Architecture",
 
         TZ_POSIX_STRING => "TZ-POSIX String",
         TZ_DATABASE_STRING => "TZ-Database String",
+        IPV6_ONLY_PREFERRED => "IPv6-Only Preferred",
+        CAPTIVE_PORTAL => "Captive-Portal",
+        AUTO_CONFIGURE => "Auto-Configure",
+        DOMAIN_SEARCH => "Domain Search",
         CLASSLESS_ROUTE_FORMAT => "Classless Route Format",
+        V_I_VENDOR_CLASS => "V-I Vendor Class",
+        V_I_VENDOR_SPECIFIC_INFORMATION => "V-I Vendor-Specific Information",
+        FORCERENEW_NONCE_CAPABLE => "Forcerenew Nonce Capable",
+        MS_CLASSLESS_STATIC_ROUTE => "Microsoft Classless Static Route",
+
+        _ => return None,
+    })
+}
 
+/// Name of the option in text, as ISC dhcpd spells it, such as "routers" for option 3.
+/// Custom options are named after their definition instead.
+pub fn short_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        SUBNET_MASK => "subnet-mask",
+        TIME_OFFSET => "time-offset",
+        ROUTER => "routers",
+        TIME_SERVER => "time-servers",
+        NAME_SERVER => "ien116-name-servers",
+        DOMAIN_NAME_SERVER => "domain-name-servers",
+        LOG_SERVER => "log-servers",
+        COOKIE_SERVER => "cookie-servers",
+        LPR_SERVER => "lpr-servers",
+        IMPRESS_SERVER => "impress-servers",
+        RESOURCE_LOCATION_SERVER => "resource-location-servers",
+        HOST_NAME => "host-name",
+        BOOT_FILE_SIZE => "boot-size",
+        MERIT_DUMP_FILE => "merit-dump",
+        DOMAIN_NAME => "domain-name",
+        SWAP_SERVER => "swap-server",
+        ROOT_PATH => "root-path",
+        EXTENSIONS_PATH => "extensions-path",
+        IP_FORWARDING_ENABLE_DISABLE => "ip-forwarding",
+        NON_LOCAL_SOURCE_ROUTING_ENABLE_DISABLE => "non-local-source-routing",
+        POLICY_FILTER => "policy-filter",
+        MAXIMUM_DATAGRAM_REASSEMBLY_SIZE => "max-dgram-reassembly",
+        DEFAULT_IP_TIME_TO_LIVE => "default-ip-ttl",
+        PATH_MTU_AGING_TIMEOUT => "path-mtu-aging-timeout",
+        PATH_MTU_PLATEAU_TABLE => "path-mtu-plateau-table",
+        INTERFACE_MTU => "interface-mtu",
+        ALL_SUBNETS_ARE_LOCAL => "all-subnets-local",
+        BROADCAST_ADDRESS => "broadcast-address",
+        PERFORM_MASK_DISCOVERY => "perform-mask-discovery",
+        MASK_SUPPLIER => "mask-supplier",
+        PERFORM_ROUTER_DISCOVERY => "router-discovery",
+        ROUTER_SOLICITATION_ADDRESS => "router-solicitation-address",
+        STATIC_ROUTE => "static-routes",
+        TRAILER_ENCAPSULATION => "trailer-encapsulation",
+        ARP_CACHE_TIMEOUT => "arp-cache-timeout",
+        ETHERNET_ENCAPSULATION => "ieee802-3-encapsulation",
+        TCP_DEFAULT_TTL => "default-tcp-ttl",
+        TCP_KEEPALIVE_INTERVAL => "tcp-keepalive-interval",
+        TCP_KEEPALIVE_GARBAGE => "tcp-keepalive-garbage",
+        NETWORK_INFORMATION_SERVICE_DOMAIN => "nis-domain",
+        NETWORK_INFORMATION_SERVERS => "nis-servers",
+        NETWORK_TIME_PROTOCOL_SERVERS => "ntp-servers",
+        VENDOR_SPECIFIC_INFORMATION => "vendor-encapsulated-options",
+        NETBIOS_OVER_TCPIP_NAME_SERVER => "netbios-name-servers",
+        NETBIOS_OVER_TCPIP_DATAGRAM_DISTRIBUTION_SERVER => "netbios-dd-server",
+        NETBIOS_OVER_TCPIP_NODE_TYPE => "netbios-node-type",
+        NETBIOS_OVER_TCPIP_SCOPE => "netbios-scope",
+        XWINDOW_SYSTEM_FONT_SERVER => "font-servers",
+        XWINDOW_SYSTEM_DISPLAY_MANAGER => "x-display-manager",
+        NETWORK_INFORMATION_SERVICEPLUS_DOMAIN => "nisplus-domain",
+        NETWORK_INFORMATION_SERVICEPLUS_SERVERS => "nisplus-servers",
+        MOBILE_IP_HOME_AGENT => "mobile-ip-home-agent",
+        SIMPLE_MAIL_TRANSPORT_PROTOCOL => "smtp-server",
+        POST_OFFICE_PROTOCOL_SERVER => "pop-server",
+        NETWORK_NEWS_TRANSPORT_PROTOCOL => "nntp-server",
+        DEFAULT_WORLD_WIDE_WEB_SERVER => "www-server",
+        DEFAULT_FINGER_SERVER => "finger-server",
+        DEFAULT_INTERNET_RELAY_CHAT_SERVER => "irc-server",
+        STREETTALK_SERVER => "streettalk-server",
+        STREETTALK_DIRECTORY_ASSISTANCE => "streettalk-directory-assistance-server",
+        RELAY_AGENT_INFORMATION => "relay-agent-information",
+        REQUESTED_IP_ADDRESS => "dhcp-requested-address",
+        IP_ADDRESS_LEASE_TIME => "dhcp-lease-time",
+        OVERLOAD => "dhcp-option-overload",
+        DHCP_MESSAGE_TYPE => "dhcp-message-type",
+        SERVER_IDENTIFIER => "dhcp-server-identifier",
+        PARAMETER_REQUEST_LIST => "dhcp-parameter-request-list",
+        MESSAGE => "dhcp-message",
+        MAXIMUM_DHCP_MESSAGE_SIZE => "dhcp-max-message-size",
+        RENEWAL_TIME_VALUE => "dhcp-renewal-time",
+        REBINDING_TIME_VALUE => "dhcp-rebinding-time",
+        VENDOR_CLASS_IDENTIFIER => "vendor-class-identifier",
+        CLIENT_IDENTIFIER => "dhcp-client-identifier",
+        TFTP_SERVER_NAME => "tftp-server-name",
+        BOOTFILE_NAME => "bootfile-name",
+        USER_CLASS => "user-class",
+        CLIENT_FQDN => "fqdn",
+        RAPID_COMMIT => "rapid-commit",
+        AUTHENTICATION => "authenticate",
+        CLIENT_ARCHITECTURE => "pxe-system-type",
+        TZ_POSIX_STRING => "pcode",
+        TZ_DATABASE_STRING => "tcode",
+        IPV6_ONLY_PREFERRED => "v6-only-preferred",
+        CAPTIVE_PORTAL => "captive-portal",
+        AUTO_CONFIGURE => "auto-config",
+        DOMAIN_SEARCH => "domain-search",
+        CLASSLESS_ROUTE_FORMAT => "classless-static-routes",
+        V_I_VENDOR_CLASS => "vivco",
+        V_I_VENDOR_SPECIFIC_INFORMATION => "vivso",
+        FORCERENEW_NONCE_CAPABLE => "forcerenew-nonce-capable",
+        MS_CLASSLESS_STATIC_ROUTE => "ms-classless-static-routes",
         _ => return None,
     })
 }
@@ -318,50 +1149,169 @@ pub fn title(code: u8) -> Option<&'static str> {
 /// > This option is used to convey the type of the DHCP message.  The code for this option is 53,
 /// > and its length is 1.
 ///
-#[derive(Copy, Clone, PartialEq, Debug)]
+/// Types 9 to 18 come from later extensions: FORCERENEW (RFC 3203), leasequery (RFC 4388),
+/// bulk leasequery (RFC 6926) and active leasequery (RFC 7724).
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum MessageType {
     /// Client broadcast to locate available servers.
-    Discover = 1,
+    Discover,
 
     /// Server to client in response to DHCPDISCOVER with offer of configuration parameters.
-    Offer = 2,
+    Offer,
 
     /// Client message to servers either (a) requesting offered parameters from one server and
     /// implicitly declining offers from all others, (b) confirming correctness of previously
     /// allocated address after, e.g., system reboot, or (c) extending the lease on a particular
     /// network address.
-    Request = 3,
+    Request,
 
     /// Client to server indicating network address is already in use.
-    Decline = 4,
+    Decline,
 
     /// Server to client with configuration parameters, including committed network address.
-    Ack = 5,
+    Ack,
 
     /// Server to client indicating client's notion of network address is incorrect (e.g., client
     /// has moved to new subnet) or client's lease as expired.
-    Nak = 6,
+    Nak,
 
     /// Client to server relinquishing network address and cancelling remaining lease.
-    Release = 7,
+    Release,
 
     /// Client to server, asking only for local configuration parameters; client already has
     /// externally configured network address.
-    Inform = 8,
+    Inform,
+
+    /// Server to client asking it to enter the RENEWING state.
+    ForceRenew,
+
+    /// Relay or other agent to server asking for the lease of an address or client.
+    LeaseQuery,
+
+    /// Server to agent: the address is the server's to give, but is not leased.
+    LeaseUnassigned,
+
+    /// Server to agent: the server knows nothing about the address or client.
+    LeaseUnknown,
+
+    /// Server to agent with the lease of the address or client.
+    LeaseActive,
+
+    /// Server to agent with one of many leases, over TCP.
+    BulkLeaseReply,
+
+    /// Server to agent: all leases of a bulk leasequery have been sent.
+    LeaseQueryDone,
+
+    /// Agent to server asking for lease changes as they happen, over TCP.
+    ActiveLeaseQuery,
+
+    /// Server to agent about the state of a bulk or active leasequery.
+    LeaseQueryStatus,
+
+    /// Starts TLS on an active leasequery connection.
+    Tls,
+
+    /// A type this crate does not know, kept so the packet is not dropped.
+    ///
+    /// `MessageType::try_from` only gives this for values above 18, so `Other(5)` does not
+    /// equal `Ack`. Build message types from their value with `try_from`: `validate` fails
+    /// for an `Other` holding a known type, and encoding leaves such an option out.
+    Other(u8),
 }
 
 impl MessageType {
     pub fn from(val: u8) -> Result<MessageType, String> {
-        match val {
-            1 => Ok(MessageType::Discover),
-            2 => Ok(MessageType::Offer),
-            3 => Ok(MessageType::Request),
-            4 => Ok(MessageType::Decline),
-            5 => Ok(MessageType::Ack),
-            6 => Ok(MessageType::Nak),
-            7 => Ok(MessageType::Release),
-            8 => Ok(MessageType::Inform),
-            _ => Err(format!("Invalid DHCP Message Type: {:?}", val)),
+        MessageType::try_from(val).map_err(|_| format!("Invalid DHCP Message Type: {:?}", val))
+    }
+
+    /// Value of the type in option 53.
+    pub fn code(self) -> u8 {
+        match self {
+            MessageType::Discover => 1,
+            MessageType::Offer => 2,
+            MessageType::Request => 3,
+            MessageType::Decline => 4,
+            MessageType::Ack => 5,
+            MessageType::Nak => 6,
+            MessageType::Release => 7,
+            MessageType::Inform => 8,
+            MessageType::ForceRenew => 9,
+            MessageType::LeaseQuery => 10,
+            MessageType::LeaseUnassigned => 11,
+            MessageType::LeaseUnknown => 12,
+            MessageType::LeaseActive => 13,
+            MessageType::BulkLeaseReply => 14,
+            MessageType::LeaseQueryDone => 15,
+            MessageType::ActiveLeaseQuery => 16,
+            MessageType::LeaseQueryStatus => 17,
+            MessageType::Tls => 18,
+            MessageType::Other(val) => val,
         }
     }
 }
+
+impl TryFrom<u8> for MessageType {
+    type Error = DecodeErrorKind;
+
+    /// Fails only for 0, which no message type uses.
+    fn try_from(val: u8) -> Result<MessageType, DecodeErrorKind> {
+        Ok(match val {
+            0 => return Err(DecodeErrorKind::UnrecognizedMessageType(val)),
+            1 => MessageType::Discover,
+            2 => MessageType::Offer,
+            3 => MessageType::Request,
+            4 => MessageType::Decline,
+            5 => MessageType::Ack,
+            6 => MessageType::Nak,
+            7 => MessageType::Release,
+            8 => MessageType::Inform,
+            9 => MessageType::ForceRenew,
+            10 => MessageType::LeaseQuery,
+            11 => MessageType::LeaseUnassigned,
+            12 => MessageType::LeaseUnknown,
+            13 => MessageType::LeaseActive,
+            14 => MessageType::BulkLeaseReply,
+            15 => MessageType::LeaseQueryDone,
+            16 => MessageType::ActiveLeaseQuery,
+            17 => MessageType::LeaseQueryStatus,
+            18 => MessageType::Tls,
+            _ => MessageType::Other(val),
+        })
+    }
+}
+
+impl From<MessageType> for u8 {
+    fn from(mtype: MessageType) -> u8 {
+        mtype.code()
+    }
+}
+
+/// The names RFC 2131 and its extensions use, such as "DHCPDISCOVER".
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MessageType::Discover => "DHCPDISCOVER",
+            MessageType::Offer => "DHCPOFFER",
+            MessageType::Request => "DHCPREQUEST",
+            MessageType::Decline => "DHCPDECLINE",
+            MessageType::Ack => "DHCPACK",
+            MessageType::Nak => "DHCPNAK",
+            MessageType::Release => "DHCPRELEASE",
+            MessageType::Inform => "DHCPINFORM",
+            MessageType::ForceRenew => "DHCPFORCERENEW",
+            MessageType::LeaseQuery => "DHCPLEASEQUERY",
+            MessageType::LeaseUnassigned => "DHCPLEASEUNASSIGNED",
+            MessageType::LeaseUnknown => "DHCPLEASEUNKNOWN",
+            MessageType::LeaseActive => "DHCPLEASEACTIVE",
+            MessageType::BulkLeaseReply => "DHCPBULKLEASEREPLY",
+            MessageType::LeaseQueryDone => "DHCPLEASEQUERYDONE",
+            MessageType::ActiveLeaseQuery => "DHCPACTIVELEASEQUERY",
+            MessageType::LeaseQueryStatus => "DHCPLEASEQUERYSTATUS",
+            MessageType::Tls => "DHCPTLS",
+            MessageType::Other(val) => return write!(f, "DHCP message type {}", val),
+        };
+        f.write_str(name)
+    }
+}
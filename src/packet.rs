@@ -1,298 +1,3082 @@
+use crate::custom::{OptionRegistry, OptionType, OptionValue, NO_DEFINITIONS};
 use crate::options::*;
 
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
 use std::net::Ipv4Addr;
 
-pub enum CustomErr<I> {
-    NomError((I, ErrorKind)),
+/// Why a packet could not be decoded.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DecodeErrorKind {
+    /// The packet ended inside the fixed BOOTP header.
+    Truncated,
+    /// `op` was neither BOOTREQUEST nor BOOTREPLY.
+    InvalidOp(u8),
+    /// `hlen` is longer than the `chaddr` field.
+    InvalidHlen(u8),
+    /// The options area does not start with the DHCP magic cookie.
+    BadCookie,
+    /// An option's length runs past the end of the area holding it.
+    TruncatedOption,
+    /// An option's data has a length its type does not allow.
+    InvalidLength(usize),
+    /// A DHCP message type of 0, which no message uses.
+    UnrecognizedMessageType(u8),
+    /// An option's value is outside the range its type allows.
+    InvalidValue,
     NonUtf8String,
-    UnrecognizedMessageType,
-    InvalidHlen,
+    /// The END option was found where an option was expected.
+    EndOfOptions,
 }
 
-pub enum ErrorKind {
-    Tag,
-    MapRes,
-    ManyTill,
-    Eof,
-    Custom(u32),
+/// Error returned by `Packet::from`, locating the problem within the packet.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodeError {
+    /// Byte offset into the packet; for an option, the offset of its (first) code byte.
+    pub offset: usize,
+    /// Code of the option being parsed, if any.
+    pub option: Option<u8>,
+    pub kind: DecodeErrorKind,
 }
 
-type IResult<I, O> = Result<(I, O), CustomErr<I>>;
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("packet shorter than the BOOTP header"),
+            Self::InvalidOp(op) => write!(f, "invalid op {}", op),
+            Self::InvalidHlen(hlen) => write!(f, "invalid hlen {}", hlen),
+            Self::BadCookie => f.write_str("missing DHCP magic cookie"),
+            Self::TruncatedOption => f.write_str("option runs past the end of its area"),
+            Self::InvalidLength(len) => write!(f, "invalid option length {}", len),
+            Self::UnrecognizedMessageType(t) => write!(f, "unrecognized message type {}", t),
+            Self::InvalidValue => f.write_str("invalid option value"),
+            Self::NonUtf8String => f.write_str("string is not valid UTF-8"),
+            Self::EndOfOptions => f.write_str("end of options"),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.option {
+            Some(code) => write!(
+                f,
+                "{} in option {} at offset {}",
+                self.kind, code, self.offset
+            ),
+            None => write!(f, "{} at offset {}", self.kind, self.offset),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// How the decoder treats options it cannot make sense of.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum DecodeMode {
+    /// Keep options whose data does not match their type as `DhcpOption::Unrecognized`,
+    /// and ignore an option that runs past the end of its area along with anything after it.
+    #[default]
+    Lenient,
+    /// Reject the packet on any malformed option, or if it has no magic cookie and
+    /// something other than zeros in its vendor area.
+    Strict,
+}
+
+type IResult<I, O> = Result<(I, O), (I, DecodeErrorKind)>;
+
+/// Broadcast bit of the BOOTP `flags` field (RFC 2131 section 2, figure 2).
+pub const BROADCAST_FLAG: u16 = 0x8000;
+
+// Hardware types (`htype`) from the IANA ARP parameters registry.
+pub const HTYPE_ETHERNET: u8 = 1;
+pub const HTYPE_IEEE802: u8 = 6;
+pub const HTYPE_INFINIBAND: u8 = 32;
+
+/// Longest hardware address that fits in the `chaddr` field.
+pub const MAX_HLEN: usize = 16;
+
+/// What identifies a client for lease lookups: its client identifier if it sent
+/// one, otherwise its hardware address (RFC 2131, section 4.2).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ClientKey {
+    ClientIdentifier(ClientIdentifier),
+    HardwareAddr(HardwareAddr),
+}
+
+/// Client hardware address: the `htype` together with the first `hlen` bytes of `chaddr`.
+///
+/// Ethernet uses 6 bytes, IEEE 802 networks may use up to 16, and InfiniBand
+/// (RFC 4390) sends an empty address and identifies itself through the client-identifier.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HardwareAddr {
+    htype: u8,
+    len: u8,
+    addr: [u8; MAX_HLEN],
+}
+
+impl HardwareAddr {
+    /// Returns `None` if `addr` is longer than `MAX_HLEN` bytes.
+    pub fn new(htype: u8, addr: &[u8]) -> Option<HardwareAddr> {
+        if addr.len() > MAX_HLEN {
+            return None;
+        }
+        let mut buf = [0; MAX_HLEN];
+        buf[..addr.len()].copy_from_slice(addr);
+        Some(HardwareAddr {
+            htype,
+            len: addr.len() as u8,
+            addr: buf,
+        })
+    }
+
+    pub fn ethernet(mac: [u8; 6]) -> HardwareAddr {
+        let mut addr = [0; MAX_HLEN];
+        addr[..6].copy_from_slice(&mac);
+        HardwareAddr {
+            htype: HTYPE_ETHERNET,
+            len: 6,
+            addr,
+        }
+    }
+
+    pub fn htype(&self) -> u8 {
+        self.htype
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.addr[..self.len as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Formats the address as colon separated hex pairs.
+impl fmt::Display for HardwareAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.as_bytes().iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HardwareAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HardwareAddr({}, {})", self.htype, self)
+    }
+}
 
 /// DHCP Packet Structure
-#[derive(Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Packet {
     pub reply: bool, // false = request, true = reply
     pub hops: u8,
     pub xid: u32, // Random identifier
     pub secs: u16,
-    pub broadcast: bool,
+    pub flags: u16, // Full flags word, see BROADCAST_FLAG
     pub ciaddr: Ipv4Addr,
     pub yiaddr: Ipv4Addr,
     pub siaddr: Ipv4Addr,
     pub giaddr: Ipv4Addr,
-    pub chaddr: [u8; 6],
+    pub chaddr: HardwareAddr,
+    pub sname: [u8; 64],       // Optional server host name, null terminated
+    pub file: [u8; 128],       // Boot file name, null terminated
+    pub vend: Option<Vec<u8>>, // Vendor area of a BOOTP packet without the magic cookie
     pub options: Vec<DhcpOption>,
 }
 
 fn decode_reply(input: &[u8]) -> IResult<&[u8], bool> {
-    let (input, reply) = custom_take(1usize)(input)?;
-    Ok((
-        input,
-        match reply[0] {
-            BOOT_REPLY => true,
-            BOOT_REQUEST => false,
-            _ => {
-                // @TODO: Throw an error
-                false
-            }
-        },
-    ))
+    let (rest, op) = custom_be_u8(input)?;
+    match op {
+        BOOT_REPLY => Ok((rest, true)),
+        BOOT_REQUEST => Ok((rest, false)),
+        _ => Err((input, DecodeErrorKind::InvalidOp(op))),
+    }
 }
 
 fn decode_ipv4(p: &[u8]) -> IResult<&[u8], Ipv4Addr> {
     let (input, addr) = custom_take(4usize)(p)?;
     Ok((input, Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3])))
 }
-fn custom_many0<I, O, F>(mut f: F) -> impl FnMut(I) -> IResult<I, Vec<O>>
-where
-    I: Clone + PartialEq,
-    F: FnMut(I) -> IResult<I, O>,
-{
-    move |input: I| {
-        let mut acc = Vec::new();
-        let mut remaining = input.clone();
+/// Parses a non-empty list of IPv4 addresses filling the whole input.
+fn decode_ipv4s(p: &[u8]) -> IResult<&[u8], Vec<Ipv4Addr>> {
+    if p.is_empty() || p.len() % 4 != 0 {
+        return Err((p, DecodeErrorKind::InvalidLength(p.len())));
+    }
+    let addrs = p
+        .chunks_exact(4)
+        .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+        .collect();
+    Ok((&p[p.len()..], addrs))
+}
 
-        loop {
-            match f(remaining.clone()) {
-                Ok((input, o)) => {
-                    if input == remaining {
-                        return Ok((input, acc));
-                    }
-                    acc.push(o);
-                    remaining = input;
-                }
-                Err(CustomErr::NomError(_)) => return Ok((remaining, acc)),
-                Err(e) => return Err(e),
-            }
+/// Parses a list of address pairs, such as destination and router, filling the whole input.
+fn decode_ipv4_pairs(p: &[u8]) -> IResult<&[u8], Vec<(Ipv4Addr, Ipv4Addr)>> {
+    let (rest, addrs) = decode_ipv4s(p)?;
+    if addrs.len() % 2 != 0 {
+        return Err((p, DecodeErrorKind::InvalidLength(p.len())));
+    }
+    let pairs = addrs.chunks_exact(2).map(|a| (a[0], a[1])).collect();
+    Ok((rest, pairs))
+}
+
+/// Parses the compact route descriptors of RFC 3442: prefix length, the significant
+/// octets of the destination, then the router. At least one route is required.
+fn decode_classless_routes(p: &[u8]) -> IResult<&[u8], Vec<(Ipv4Net, Ipv4Addr)>> {
+    if p.is_empty() {
+        return Err((p, DecodeErrorKind::InvalidLength(0)));
+    }
+    let mut routes = Vec::new();
+    let mut input = p;
+    while !input.is_empty() {
+        let (rest, prefix_len) = custom_be_u8(input)?;
+        if prefix_len > 32 {
+            return Err((input, DecodeErrorKind::InvalidValue));
         }
+        let (rest, dest) = custom_take((prefix_len as usize).div_ceil(8))(rest)?;
+        let (rest, router) = decode_ipv4(rest)?;
+        let mut octets = [0; 4];
+        octets[..dest.len()].copy_from_slice(dest);
+        match Ipv4Net::new(Ipv4Addr::from(octets), prefix_len) {
+            Some(net) => routes.push((net, router)),
+            None => return Err((input, DecodeErrorKind::InvalidValue)),
+        }
+        input = rest;
     }
+    Ok((input, routes))
 }
-pub fn decode_option(input: &[u8]) -> IResult<&[u8], DhcpOption> {
-    let (input, code) = custom_be_u8(input)?;
-    assert!(code != END);
 
-    let (input, len) = custom_be_u8(input)?;
-    let (input, data) = custom_take(len.into())(input)?;
-    let option = match code {
-        DHCP_MESSAGE_TYPE => {
-            DhcpOption::DhcpMessageType(match MessageType::from(custom_be_u8(data)?.1) {
-                Ok(x) => x,
-                Err(_) => return Err(CustomErr::UnrecognizedMessageType),
-            })
+/// Parses the sub-options of Relay Agent Information, of which there must be at least one.
+fn decode_relay_sub_options(p: &[u8]) -> IResult<&[u8], Vec<RelayAgentSubOption>> {
+    if p.is_empty() {
+        return Err((p, DecodeErrorKind::InvalidLength(0)));
+    }
+    let mut subs = Vec::new();
+    let mut input = p;
+    while !input.is_empty() {
+        let (rest, code) = custom_be_u8(input)?;
+        let (rest, len) = custom_be_u8(rest)?;
+        let (rest, data) = custom_take(len as usize)(rest)?;
+        let sub = match decode_relay_sub_option(code, data) {
+            Ok(sub) => sub,
+            Err(kind) => return Err((input, kind)),
+        };
+        subs.push(sub);
+        input = rest;
+    }
+    Ok((input, subs))
+}
+
+fn decode_relay_sub_option(code: u8, data: &[u8]) -> Result<RelayAgentSubOption, DecodeErrorKind> {
+    Ok(match code {
+        AGENT_CIRCUIT_ID => RelayAgentSubOption::CircuitId(all(data, decode_bytes)?),
+        AGENT_REMOTE_ID => RelayAgentSubOption::RemoteId(all(data, decode_bytes)?),
+        LINK_SELECTION => RelayAgentSubOption::LinkSelection(all(data, decode_ipv4)?),
+        SUBSCRIBER_ID => RelayAgentSubOption::SubscriberId(all(data, decode_string)?),
+        RELAY_AGENT_FLAGS => RelayAgentSubOption::RelayAgentFlags(all(data, custom_be_u8)?),
+        SERVER_IDENTIFIER_OVERRIDE => {
+            RelayAgentSubOption::ServerIdentifierOverride(all(data, decode_ipv4)?)
         }
-        SERVER_IDENTIFIER => DhcpOption::ServerIdentifier(decode_ipv4(data)?.1),
-        PARAMETER_REQUEST_LIST => DhcpOption::ParameterRequestList(data.to_vec()),
-        REQUESTED_IP_ADDRESS => DhcpOption::RequestedIpAddress(decode_ipv4(data)?.1),
-        HOST_NAME => DhcpOption::HostName(match std::str::from_utf8(data) {
-            Ok(s) => s.to_string(),
-            Err(_) => return Err(CustomErr::NonUtf8String),
-        }),
-        ROUTER => DhcpOption::Router(custom_many0(decode_ipv4)(data)?.1),
-        DOMAIN_NAME_SERVER => DhcpOption::DomainNameServer(custom_many0(decode_ipv4)(data)?.1),
-        IP_ADDRESS_LEASE_TIME => DhcpOption::IpAddressLeaseTime(custom_be_u32(data)?.1),
-        SUBNET_MASK => DhcpOption::SubnetMask(decode_ipv4(data)?.1),
-        MESSAGE => DhcpOption::Message(match std::str::from_utf8(data) {
-            Ok(s) => s.to_string(),
-            Err(_) => return Err(CustomErr::NonUtf8String),
-        }),
-        _ => DhcpOption::Unrecognized(RawDhcpOption {
+        RELAY_SOURCE_PORT if data.is_empty() => RelayAgentSubOption::RelaySourcePort,
+        RELAY_SOURCE_PORT => return Err(DecodeErrorKind::InvalidLength(data.len())),
+        _ => RelayAgentSubOption::Unrecognized(RawDhcpOption {
             code,
             data: data.to_vec(),
         }),
-    };
-    Ok((input, option))
+    })
 }
-fn custom_take<'a>(n: usize) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
-    move |input: &'a [u8]| {
-        if input.len() >= n {
-            Ok((&input[n..], &input[0..n]))
-        } else {
-            Err(CustomErr::InvalidHlen)
-        }
+
+fn decode_client_identifier(data: &[u8]) -> Result<ClientIdentifier, DecodeErrorKind> {
+    if data.len() < 2 {
+        return Err(DecodeErrorKind::InvalidLength(data.len()));
     }
+    Ok(match data[0] {
+        CLIENT_IDENTIFIER_OPAQUE => ClientIdentifier::Opaque(data[1..].to_vec()),
+        CLIENT_IDENTIFIER_DUID => {
+            if data.len() < 1 + 4 {
+                return Err(DecodeErrorKind::InvalidLength(data.len()));
+            }
+            ClientIdentifier::Duid {
+                iaid: u32::from_be_bytes([data[1], data[2], data[3], data[4]]),
+                duid: decode_duid(&data[5..])?,
+            }
+        }
+        htype => ClientIdentifier::HardwareAddr {
+            htype,
+            addr: data[1..].to_vec(),
+        },
+    })
 }
 
-fn custom_tag<'a>(tag: &'static [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
-    move |input: &'a [u8]| {
-        if input.starts_with(tag) {
-            Ok((&input[tag.len()..], &input[..tag.len()]))
-        } else {
-            Err(CustomErr::NomError((input, ErrorKind::Tag)))
-        }
+/// Parses a DUID, which is at most 128 octets after its type.
+fn decode_duid(data: &[u8]) -> Result<Duid, DecodeErrorKind> {
+    if data.len() < 2 || data.len() > 2 + 128 {
+        return Err(DecodeErrorKind::InvalidLength(data.len()));
     }
+    let duid_type = u16::from_be_bytes([data[0], data[1]]);
+    let rest = &data[2..];
+    let be_u16 = |at: usize| u16::from_be_bytes([rest[at], rest[at + 1]]);
+    let be_u32 =
+        |at: usize| u32::from_be_bytes([rest[at], rest[at + 1], rest[at + 2], rest[at + 3]]);
+    Ok(match duid_type {
+        DUID_LLT if rest.len() >= 6 => Duid::LinkLayerTime {
+            hardware_type: be_u16(0),
+            time: be_u32(2),
+            addr: rest[6..].to_vec(),
+        },
+        DUID_EN if rest.len() >= 4 => Duid::Enterprise {
+            enterprise_number: be_u32(0),
+            id: rest[4..].to_vec(),
+        },
+        DUID_LL if rest.len() >= 2 => Duid::LinkLayer {
+            hardware_type: be_u16(0),
+            addr: rest[2..].to_vec(),
+        },
+        DUID_UUID if rest.len() == 16 => {
+            let mut uuid = [0; 16];
+            uuid.copy_from_slice(rest);
+            Duid::Uuid(uuid)
+        }
+        DUID_LLT | DUID_EN | DUID_LL | DUID_UUID => {
+            return Err(DecodeErrorKind::InvalidLength(data.len()))
+        }
+        _ => Duid::Other {
+            duid_type,
+            data: rest.to_vec(),
+        },
+    })
 }
-fn custom_be_u8(input: &[u8]) -> IResult<&[u8], u8> {
-    if input.is_empty() {
-        return Err(CustomErr::InvalidHlen);
-    }
 
-    Ok((&input[1..], input[0]))
+fn decode_client_fqdn(data: &[u8]) -> Result<ClientFqdn, DecodeErrorKind> {
+    if data.len() < 3 {
+        return Err(DecodeErrorKind::InvalidLength(data.len()));
+    }
+    let flags = data[0];
+    // Unused bits must be zero, and N excludes S.
+    if flags & 0xf0 != 0 || flags & (FQDN_FLAG_N | FQDN_FLAG_S) == FQDN_FLAG_N | FQDN_FLAG_S {
+        return Err(DecodeErrorKind::InvalidValue);
+    }
+    let (labels, fully_qualified) = if flags & FQDN_FLAG_E != 0 {
+        decode_wire_name(&data[3..])?
+    } else {
+        decode_ascii_name(&data[3..])?
+    };
+    Ok(ClientFqdn {
+        flags,
+        rcode1: data[1],
+        rcode2: data[2],
+        labels,
+        fully_qualified,
+    })
 }
 
-fn custom_be_u16(input: &[u8]) -> IResult<&[u8], u16> {
-    if input.len() < 2 {
-        return Err(CustomErr::InvalidHlen);
+/// Parses an uncompressed name in DNS wire format taking up the whole input. It is fully
+/// qualified if it ends with the root label, and partial otherwise.
+fn decode_wire_name(data: &[u8]) -> Result<(Vec<String>, bool), DecodeErrorKind> {
+    if data.len() > 255 {
+        return Err(DecodeErrorKind::InvalidLength(data.len()));
     }
-
-    let value = u16::from_be_bytes([input[0], input[1]]);
-    Ok((&input[2..], value))
+    let mut labels = Vec::new();
+    let mut input = data;
+    while let Some((&len, rest)) = input.split_first() {
+        if len == 0 {
+            if !rest.is_empty() {
+                return Err(DecodeErrorKind::InvalidLength(data.len()));
+            }
+            return Ok((labels, true));
+        }
+        let label = rest
+            .get(..len as usize)
+            .ok_or(DecodeErrorKind::InvalidLength(data.len()))?;
+        labels.push(decode_host_label(label)?);
+        input = &rest[len as usize..];
+    }
+    Ok((labels, false))
 }
-fn custom_be_u32(input: &[u8]) -> IResult<&[u8], u32> {
-    if input.len() < 4 {
-        return Err(CustomErr::InvalidHlen);
+
+/// Parses a non-empty list of non-empty instances, each preceded by its length.
+fn decode_instances(data: &[u8]) -> Result<Vec<Vec<u8>>, DecodeErrorKind> {
+    if data.is_empty() {
+        return Err(DecodeErrorKind::InvalidLength(0));
     }
+    let mut instances = Vec::new();
+    let mut input = data;
+    while let Some((&len, rest)) = input.split_first() {
+        match rest.get(..len as usize) {
+            Some(instance) if len > 0 => instances.push(instance.to_vec()),
+            _ => return Err(DecodeErrorKind::InvalidLength(data.len())),
+        }
+        input = &rest[len as usize..];
+    }
+    Ok(instances)
+}
 
-    let value = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
-    Ok((&input[4..], value))
+/// Parses code, length and data triples, of which there may be none.
+fn decode_sub_options(data: &[u8]) -> Result<Vec<RawDhcpOption>, DecodeErrorKind> {
+    let mut sub_options = Vec::new();
+    let mut input = data;
+    while !input.is_empty() {
+        let (rest, code) = custom_be_u8(input).map_err(|(_, kind)| kind)?;
+        let (rest, len) = custom_be_u8(rest).map_err(|(_, kind)| kind)?;
+        let (rest, data) = custom_take(len as usize)(rest).map_err(|(_, kind)| kind)?;
+        sub_options.push(RawDhcpOption {
+            code,
+            data: data.to_vec(),
+        });
+        input = rest;
+    }
+    Ok(sub_options)
 }
 
-/// Parses Packet from byte array
-fn decode(input: &[u8]) -> IResult<&[u8], Packet> {
-    let (options_input, input) = custom_take(236usize)(input)?;
+/// Parses the blocks of the Vendor-Identifying options (RFC 3925): enterprise number,
+/// length and data, read by `parser`. There must be at least one block.
+fn decode_enterprise_blocks<T>(
+    data: &[u8],
+    parser: impl Fn(&[u8]) -> Result<T, DecodeErrorKind>,
+) -> Result<Vec<(u32, T)>, DecodeErrorKind> {
+    let invalid_length = DecodeErrorKind::InvalidLength(data.len());
+    if data.is_empty() {
+        return Err(invalid_length);
+    }
+    let mut blocks = Vec::new();
+    let mut input = data;
+    while !input.is_empty() {
+        let (rest, enterprise) = custom_be_u32(input).map_err(|_| invalid_length.clone())?;
+        let (rest, len) = custom_be_u8(rest).map_err(|_| invalid_length.clone())?;
+        let (rest, block) = custom_take(len as usize)(rest).map_err(|_| invalid_length.clone())?;
+        let parsed = match parser(block) {
+            Err(DecodeErrorKind::Truncated) => return Err(invalid_length),
+            other => other?,
+        };
+        blocks.push((enterprise, parsed));
+        input = rest;
+    }
+    Ok(blocks)
+}
 
-    let (input, reply) = decode_reply(input)?;
-    let (input, _htype) = custom_take(1usize)(input)?;
-    let (input, hlen) = custom_be_u8(input)?;
-    let (input, hops) = custom_be_u8(input)?;
-    let (input, xid) = custom_be_u32(input)?;
-    let (input, secs) = custom_be_u16(input)?;
-    let (input, flags) = custom_be_u16(input)?;
-    let (input, ciaddr) = decode_ipv4(input)?;
-    let (input, yiaddr) = decode_ipv4(input)?;
-    let (input, siaddr) = decode_ipv4(input)?;
-    let (input, giaddr) = decode_ipv4(input)?;
+/// Parses the list of compressed domain names in option 119, gathered from all its
+/// instances so that pointers can refer to earlier ones (RFC 3397).
+fn decode_domain_search(data: &[u8]) -> Result<Vec<String>, DecodeErrorKind> {
+    if data.is_empty() {
+        return Err(DecodeErrorKind::InvalidLength(0));
+    }
+    let mut names = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (name, next) = decode_compressed_name(data, pos)?;
+        names.push(name);
+        pos = next;
+    }
+    Ok(names)
+}
 
-    if hlen != 6 {
-        return Err(CustomErr::InvalidHlen);
+/// Reads the name at `start`, returning it and the offset just past it. Every pointer
+/// must point before the previous one's target, so malicious pointers cannot loop.
+fn decode_compressed_name(data: &[u8], start: usize) -> Result<(String, usize), DecodeErrorKind> {
+    let truncated = DecodeErrorKind::InvalidLength(data.len());
+    let mut labels = Vec::new();
+    let mut wire_len = 1;
+    let mut pos = start;
+    let mut limit = start;
+    let mut end = None;
+    loop {
+        let len = *data.get(pos).ok_or_else(|| truncated.clone())?;
+        match len & 0xc0 {
+            0x00 if len == 0 => break,
+            0x00 => {
+                let label = data
+                    .get(pos + 1..pos + 1 + len as usize)
+                    .ok_or_else(|| truncated.clone())?;
+                wire_len += 1 + label.len();
+                if wire_len > 255 {
+                    return Err(DecodeErrorKind::InvalidValue);
+                }
+                labels.push(decode_host_label(label)?);
+                pos += 1 + label.len();
+            }
+            0xc0 => {
+                let low = *data.get(pos + 1).ok_or_else(|| truncated.clone())?;
+                let target = u16::from_be_bytes([len & 0x3f, low]) as usize;
+                if target >= limit {
+                    return Err(DecodeErrorKind::InvalidValue);
+                }
+                end.get_or_insert(pos + 2);
+                limit = target;
+                pos = target;
+            }
+            // The remaining label types are reserved.
+            _ => return Err(DecodeErrorKind::InvalidValue),
+        }
     }
-    let (_, chaddr) = custom_take(6usize)(input)?;
+    Ok((labels.join("."), end.unwrap_or(pos + 1)))
+}
 
-    let input = options_input;
-    let (input, _) = custom_tag(&COOKIE)(input)?;
+/// Parses a dotted name, which is fully qualified if it ends with a dot.
+fn decode_ascii_name(data: &[u8]) -> Result<(Vec<String>, bool), DecodeErrorKind> {
+    let (name, fully_qualified) = match data.split_last() {
+        Some((b'.', name)) => (name, true),
+        _ => (data, false),
+    };
+    if name.is_empty() {
+        return Ok((Vec::new(), fully_qualified));
+    }
+    let labels = name
+        .split(|&b| b == b'.')
+        .map(decode_host_label)
+        .collect::<Result<_, _>>()?;
+    Ok((labels, fully_qualified))
+}
 
-    let mut options = Vec::new();
-    let mut rest = input;
+fn decode_host_label(label: &[u8]) -> Result<String, DecodeErrorKind> {
+    match std::str::from_utf8(label) {
+        Ok(l) if is_host_label(l) => Ok(l.to_string()),
+        _ => Err(DecodeErrorKind::InvalidValue),
+    }
+}
 
-    while let Ok((new_rest, option)) = decode_option(rest) {
-        rest = new_rest;
-        options.push(option);
-        if rest.starts_with(&[END]) {
-            break;
-        }
+/// Parses a non-empty list of 16 bit values filling the whole input.
+fn decode_u16s(p: &[u8]) -> IResult<&[u8], Vec<u16>> {
+    if p.is_empty() || p.len() % 2 != 0 {
+        return Err((p, DecodeErrorKind::InvalidLength(p.len())));
     }
+    let values = p
+        .chunks_exact(2)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .collect();
+    Ok((&p[p.len()..], values))
+}
 
-    let input = rest.split_at(1).1; // Skip the END tag byte
+fn decode_bool(p: &[u8]) -> IResult<&[u8], bool> {
+    match custom_be_u8(p)? {
+        (rest, 0) => Ok((rest, false)),
+        (rest, 1) => Ok((rest, true)),
+        _ => Err((p, DecodeErrorKind::InvalidValue)),
+    }
+}
 
+/// Parses option 90: protocol, algorithm, replay detection method and value, and the
+/// authentication information filling the rest.
+fn decode_authentication(p: &[u8]) -> IResult<&[u8], Authentication> {
+    let (p, protocol) = custom_be_u8(p)?;
+    let (p, algorithm) = custom_be_u8(p)?;
+    let (p, rdm) = custom_be_u8(p)?;
+    let (p, replay) = custom_take(8usize)(p)?;
+    let mut replay_detection = [0; 8];
+    replay_detection.copy_from_slice(replay);
     Ok((
-        input,
-        Packet {
-            reply,
-            hops,
-            secs,
-            broadcast: flags & 128 == 128,
-            ciaddr,
-            yiaddr,
-            siaddr,
-            giaddr,
-            options,
-            chaddr: [
-                chaddr[0], chaddr[1], chaddr[2], chaddr[3], chaddr[4], chaddr[5],
-            ],
-            xid,
+        &p[p.len()..],
+        Authentication {
+            protocol,
+            algorithm,
+            rdm,
+            replay_detection: u64::from_be_bytes(replay_detection),
+            information: p.to_vec(),
         },
     ))
 }
 
-impl Packet {
-    pub fn from(input: &[u8]) -> Result<Packet, CustomErr<&[u8]>> {
-        Ok(decode(input)?.1)
+/// Takes the whole input as opaque data, of which there must be at least one octet.
+fn decode_bytes(p: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    if p.is_empty() {
+        return Err((p, DecodeErrorKind::InvalidLength(0)));
     }
+    Ok((&p[p.len()..], p.to_vec()))
+}
 
-    /// Extracts requested option payload from packet if available
-    pub fn option(&self, code: u8) -> Option<&DhcpOption> {
-        self.options.iter().find(|&option| option.code() == code)
+/// Rejects values below the minimum RFC 2132 gives for them.
+fn at_least<T: PartialOrd>(value: T, min: T) -> Result<T, DecodeErrorKind> {
+    if value < min {
+        return Err(DecodeErrorKind::InvalidValue);
     }
+    Ok(value)
+}
 
-    /// Convenience function for extracting a packet's message type.
-    pub fn message_type(&self) -> Result<MessageType, String> {
-        match self.option(DHCP_MESSAGE_TYPE) {
-            Some(DhcpOption::DhcpMessageType(msgtype)) => Ok(*msgtype),
-            Some(option) => Err(format![
-                "Got wrong enum code {} for DHCP_MESSAGE_TYPE",
-                option.code()
-            ]),
-            None => Err("Packet does not have MessageType option".to_string()),
+/// Parses a non-empty string filling the whole input.
+fn decode_string(p: &[u8]) -> IResult<&[u8], String> {
+    if p.is_empty() {
+        return Err((p, DecodeErrorKind::InvalidLength(0)));
+    }
+    match std::str::from_utf8(p) {
+        Ok(s) => Ok((&p[p.len()..], s.to_string())),
+        Err(_) => Err((p, DecodeErrorKind::NonUtf8String)),
+    }
+}
+
+/// Runs `parser` over the whole of an option's data, which must be used up exactly.
+fn all<'a, O>(
+    data: &'a [u8],
+    parser: impl Fn(&'a [u8]) -> IResult<&'a [u8], O>,
+) -> Result<O, DecodeErrorKind> {
+    match parser(data) {
+        Ok(([], o)) => Ok(o),
+        Ok(_) | Err((_, DecodeErrorKind::Truncated)) => {
+            Err(DecodeErrorKind::InvalidLength(data.len()))
         }
+        Err((_, kind)) => Err(kind),
     }
-    pub fn encode<'a>(&'a self, p: &'a mut [u8]) -> &[u8] {
-        let broadcast_flag = if self.broadcast { 128 } else { 0 };
-        let mut length = 240;
+}
 
-        p[..12].copy_from_slice(&[
-            if self.reply { BOOT_REPLY } else { BOOT_REQUEST },
-            1,
-            6,
-            self.hops,
-            ((self.xid >> 24) & 0xFF) as u8,
-            ((self.xid >> 16) & 0xFF) as u8,
-            ((self.xid >> 8) & 0xFF) as u8,
-            (self.xid & 0xFF) as u8,
-            (self.secs >> 8) as u8,
-            (self.secs & 255) as u8,
-            broadcast_flag,
-            0,
-        ]);
+/// Parses a single option from the start of `input`, skipping any PAD bytes before it.
+/// Fails with `EndOfOptions` if the END option is reached first.
+pub fn decode_option(input: &[u8]) -> Result<(&[u8], DhcpOption), DecodeError> {
+    let start = input.iter().position(|&b| b != PAD).unwrap_or(input.len());
+    let err = |kind| DecodeError {
+        offset: start,
+        option: input.get(start).copied(),
+        kind,
+    };
+    let (rest, (code, data)) = match decode_raw_option(&input[start..]) {
+        Ok(x) => x,
+        Err(_) if input.get(start) == Some(&END) => return Err(err(DecodeErrorKind::EndOfOptions)),
+        Err(_) => return Err(err(DecodeErrorKind::TruncatedOption)),
+    };
+    Ok((rest, decode_option_data(code, data).map_err(err)?))
+}
 
-        p[12..16].copy_from_slice(&self.ciaddr.octets());
-        p[16..20].copy_from_slice(&self.yiaddr.octets());
-        p[20..24].copy_from_slice(&self.siaddr.octets());
-        p[24..28].copy_from_slice(&self.giaddr.octets());
-        p[28..34].copy_from_slice(&self.chaddr);
-        p[34..236].fill(0);
-        p[236..240].copy_from_slice(&COOKIE);
+/// Splits a single option into its code and data, without interpreting the data.
+/// PAD and END carry no length and are not options in this sense.
+fn decode_raw_option(input: &[u8]) -> IResult<&[u8], (u8, &[u8])> {
+    let (rest, code) = custom_be_u8(input)?;
+    if code == PAD || code == END {
+        return Err((input, DecodeErrorKind::EndOfOptions));
+    }
 
-        for option in &self.options {
-            let option = option.to_raw();
-            let option_len = option.data.len();
-            if length + 2 + option_len >= 272 {
-                break;
+    let (rest, len) = custom_be_u8(rest)?;
+    let (rest, data) = custom_take(len.into())(rest)?;
+    Ok((rest, (code, data)))
+}
+
+/// Decodes the data of an option the crate knows itself. Custom options are `Unrecognized`.
+pub(crate) fn decode_option_data(code: u8, data: &[u8]) -> Result<DhcpOption, DecodeErrorKind> {
+    decode_option_data_with(code, data, &NO_DEFINITIONS)
+}
+
+/// Decodes the data of an option, custom ones as `registry` defines them.
+pub(crate) fn decode_option_data_with(
+    code: u8,
+    data: &[u8],
+    registry: &OptionRegistry,
+) -> Result<DhcpOption, DecodeErrorKind> {
+    Ok(match code {
+        DHCP_MESSAGE_TYPE => {
+            DhcpOption::DhcpMessageType(MessageType::try_from(all(data, custom_be_u8)?)?)
+        }
+        SERVER_IDENTIFIER => DhcpOption::ServerIdentifier(all(data, decode_ipv4)?),
+        PARAMETER_REQUEST_LIST => DhcpOption::ParameterRequestList(all(data, decode_bytes)?),
+        REQUESTED_IP_ADDRESS => DhcpOption::RequestedIpAddress(all(data, decode_ipv4)?),
+        HOST_NAME => DhcpOption::HostName(all(data, decode_string)?),
+        ROUTER => DhcpOption::Router(all(data, decode_ipv4s)?),
+        DOMAIN_NAME_SERVER => DhcpOption::DomainNameServer(all(data, decode_ipv4s)?),
+        IP_ADDRESS_LEASE_TIME => DhcpOption::IpAddressLeaseTime(all(data, custom_be_u32)?),
+        MAXIMUM_DHCP_MESSAGE_SIZE => DhcpOption::MaximumDhcpMessageSize(all(data, custom_be_u16)?),
+        SUBNET_MASK => DhcpOption::SubnetMask(all(data, decode_ipv4)?),
+        MESSAGE => DhcpOption::Message(all(data, decode_string)?),
+        TIME_OFFSET => DhcpOption::TimeOffset(all(data, custom_be_u32)? as i32),
+        TIME_SERVER => DhcpOption::TimeServer(all(data, decode_ipv4s)?),
+        NAME_SERVER => DhcpOption::NameServer(all(data, decode_ipv4s)?),
+        LOG_SERVER => DhcpOption::LogServer(all(data, decode_ipv4s)?),
+        COOKIE_SERVER => DhcpOption::CookieServer(all(data, decode_ipv4s)?),
+        LPR_SERVER => DhcpOption::LprServer(all(data, decode_ipv4s)?),
+        IMPRESS_SERVER => DhcpOption::ImpressServer(all(data, decode_ipv4s)?),
+        RESOURCE_LOCATION_SERVER => DhcpOption::ResourceLocationServer(all(data, decode_ipv4s)?),
+        BOOT_FILE_SIZE => DhcpOption::BootFileSize(all(data, custom_be_u16)?),
+        MERIT_DUMP_FILE => DhcpOption::MeritDumpFile(all(data, decode_string)?),
+        DOMAIN_NAME => DhcpOption::DomainName(all(data, decode_string)?),
+        SWAP_SERVER => DhcpOption::SwapServer(all(data, decode_ipv4)?),
+        ROOT_PATH => DhcpOption::RootPath(all(data, decode_string)?),
+        EXTENSIONS_PATH => DhcpOption::ExtensionsPath(all(data, decode_string)?),
+        IP_FORWARDING_ENABLE_DISABLE => DhcpOption::IpForwarding(all(data, decode_bool)?),
+        NON_LOCAL_SOURCE_ROUTING_ENABLE_DISABLE => {
+            DhcpOption::NonLocalSourceRouting(all(data, decode_bool)?)
+        }
+        POLICY_FILTER => DhcpOption::PolicyFilter(all(data, decode_ipv4_pairs)?),
+        MAXIMUM_DATAGRAM_REASSEMBLY_SIZE => {
+            DhcpOption::MaximumDatagramReassemblySize(at_least(all(data, custom_be_u16)?, 576)?)
+        }
+        DEFAULT_IP_TIME_TO_LIVE => {
+            DhcpOption::DefaultIpTimeToLive(at_least(all(data, custom_be_u8)?, 1)?)
+        }
+        PATH_MTU_AGING_TIMEOUT => DhcpOption::PathMtuAgingTimeout(all(data, custom_be_u32)?),
+        PATH_MTU_PLATEAU_TABLE => {
+            let mtus = all(data, decode_u16s)?;
+            for &mtu in &mtus {
+                at_least(mtu, MIN_MTU)?;
+            }
+            DhcpOption::PathMtuPlateauTable(mtus)
+        }
+        INTERFACE_MTU => DhcpOption::InterfaceMtu(at_least(all(data, custom_be_u16)?, MIN_MTU)?),
+        ALL_SUBNETS_ARE_LOCAL => DhcpOption::AllSubnetsAreLocal(all(data, decode_bool)?),
+        BROADCAST_ADDRESS => DhcpOption::BroadcastAddress(all(data, decode_ipv4)?),
+        PERFORM_MASK_DISCOVERY => DhcpOption::PerformMaskDiscovery(all(data, decode_bool)?),
+        MASK_SUPPLIER => DhcpOption::MaskSupplier(all(data, decode_bool)?),
+        PERFORM_ROUTER_DISCOVERY => DhcpOption::PerformRouterDiscovery(all(data, decode_bool)?),
+        ROUTER_SOLICITATION_ADDRESS => {
+            DhcpOption::RouterSolicitationAddress(all(data, decode_ipv4)?)
+        }
+        STATIC_ROUTE => {
+            let routes = all(data, decode_ipv4_pairs)?;
+            // The default route is an illegal destination for a static route.
+            if routes.iter().any(|(dest, _)| dest.is_unspecified()) {
+                return Err(DecodeErrorKind::InvalidValue);
+            }
+            DhcpOption::StaticRoute(routes)
+        }
+        TRAILER_ENCAPSULATION => DhcpOption::TrailerEncapsulation(all(data, decode_bool)?),
+        ARP_CACHE_TIMEOUT => DhcpOption::ArpCacheTimeout(all(data, custom_be_u32)?),
+        ETHERNET_ENCAPSULATION => DhcpOption::EthernetEncapsulation(all(data, decode_bool)?),
+        TCP_DEFAULT_TTL => DhcpOption::TcpDefaultTtl(at_least(all(data, custom_be_u8)?, 1)?),
+        TCP_KEEPALIVE_INTERVAL => DhcpOption::TcpKeepaliveInterval(all(data, custom_be_u32)?),
+        TCP_KEEPALIVE_GARBAGE => DhcpOption::TcpKeepaliveGarbage(all(data, decode_bool)?),
+        NETWORK_INFORMATION_SERVICE_DOMAIN => {
+            DhcpOption::NetworkInformationServiceDomain(all(data, decode_string)?)
+        }
+        NETWORK_INFORMATION_SERVERS => {
+            DhcpOption::NetworkInformationServers(all(data, decode_ipv4s)?)
+        }
+        NETWORK_TIME_PROTOCOL_SERVERS => {
+            DhcpOption::NetworkTimeProtocolServers(all(data, decode_ipv4s)?)
+        }
+        VENDOR_SPECIFIC_INFORMATION => {
+            DhcpOption::VendorSpecificInformation(all(data, decode_bytes)?)
+        }
+        NETBIOS_OVER_TCPIP_NAME_SERVER => DhcpOption::NetbiosNameServer(all(data, decode_ipv4s)?),
+        NETBIOS_OVER_TCPIP_DATAGRAM_DISTRIBUTION_SERVER => {
+            DhcpOption::NetbiosDatagramDistributionServer(all(data, decode_ipv4s)?)
+        }
+        NETBIOS_OVER_TCPIP_NODE_TYPE => match all(data, custom_be_u8)? {
+            t @ (1 | 2 | 4 | 8) => DhcpOption::NetbiosNodeType(t),
+            _ => return Err(DecodeErrorKind::InvalidValue),
+        },
+        NETBIOS_OVER_TCPIP_SCOPE => DhcpOption::NetbiosScope(all(data, decode_string)?),
+        XWINDOW_SYSTEM_FONT_SERVER => DhcpOption::XWindowSystemFontServer(all(data, decode_ipv4s)?),
+        XWINDOW_SYSTEM_DISPLAY_MANAGER => {
+            DhcpOption::XWindowSystemDisplayManager(all(data, decode_ipv4s)?)
+        }
+        NETWORK_INFORMATION_SERVICEPLUS_DOMAIN => {
+            DhcpOption::NetworkInformationServicePlusDomain(all(data, decode_string)?)
+        }
+        NETWORK_INFORMATION_SERVICEPLUS_SERVERS => {
+            DhcpOption::NetworkInformationServicePlusServers(all(data, decode_ipv4s)?)
+        }
+        // Unlike other address lists, the home agent list may be empty.
+        MOBILE_IP_HOME_AGENT if data.is_empty() => DhcpOption::MobileIpHomeAgent(Vec::new()),
+        MOBILE_IP_HOME_AGENT => DhcpOption::MobileIpHomeAgent(all(data, decode_ipv4s)?),
+        SIMPLE_MAIL_TRANSPORT_PROTOCOL => DhcpOption::SmtpServer(all(data, decode_ipv4s)?),
+        POST_OFFICE_PROTOCOL_SERVER => DhcpOption::Pop3Server(all(data, decode_ipv4s)?),
+        NETWORK_NEWS_TRANSPORT_PROTOCOL => DhcpOption::NntpServer(all(data, decode_ipv4s)?),
+        DEFAULT_WORLD_WIDE_WEB_SERVER => DhcpOption::WwwServer(all(data, decode_ipv4s)?),
+        DEFAULT_FINGER_SERVER => DhcpOption::FingerServer(all(data, decode_ipv4s)?),
+        DEFAULT_INTERNET_RELAY_CHAT_SERVER => DhcpOption::IrcServer(all(data, decode_ipv4s)?),
+        STREETTALK_SERVER => DhcpOption::StreetTalkServer(all(data, decode_ipv4s)?),
+        STREETTALK_DIRECTORY_ASSISTANCE => {
+            DhcpOption::StreetTalkDirectoryAssistanceServer(all(data, decode_ipv4s)?)
+        }
+        RELAY_AGENT_INFORMATION => {
+            DhcpOption::RelayAgentInformation(all(data, decode_relay_sub_options)?)
+        }
+        OVERLOAD => match all(data, custom_be_u8)? {
+            o @ 1..=3 => DhcpOption::Overload(o),
+            _ => return Err(DecodeErrorKind::InvalidValue),
+        },
+        RENEWAL_TIME_VALUE => DhcpOption::RenewalTimeValue(all(data, custom_be_u32)?),
+        REBINDING_TIME_VALUE => DhcpOption::RebindingTimeValue(all(data, custom_be_u32)?),
+        VENDOR_CLASS_IDENTIFIER => DhcpOption::VendorClassIdentifier(all(data, decode_bytes)?),
+        CLIENT_IDENTIFIER => DhcpOption::ClientIdentifier(decode_client_identifier(data)?),
+        CLIENT_FQDN => DhcpOption::ClientFqdn(decode_client_fqdn(data)?),
+        DOMAIN_SEARCH => DhcpOption::DomainSearch(decode_domain_search(data)?),
+        RAPID_COMMIT if data.is_empty() => DhcpOption::RapidCommit,
+        RAPID_COMMIT => return Err(DecodeErrorKind::InvalidLength(data.len())),
+        IPV6_ONLY_PREFERRED => DhcpOption::Ipv6OnlyPreferred(all(data, custom_be_u32)?),
+        CAPTIVE_PORTAL => DhcpOption::CaptivePortal(all(data, decode_string)?),
+        AUTO_CONFIGURE => DhcpOption::AutoConfigure(all(data, decode_bool)?),
+        AUTHENTICATION => DhcpOption::Authentication(all(data, decode_authentication)?),
+        FORCERENEW_NONCE_CAPABLE => DhcpOption::ForcerenewNonceCapable(all(data, decode_bytes)?),
+        TFTP_SERVER_NAME => DhcpOption::TftpServerName(all(data, decode_string)?),
+        BOOTFILE_NAME => DhcpOption::BootfileName(all(data, decode_string)?),
+        USER_CLASS => match decode_instances(data) {
+            Ok(instances) => DhcpOption::UserClass(instances),
+            // A single string without the length octet, as iPXE sends.
+            Err(_) if !data.is_empty() && data.len() <= 255 => {
+                DhcpOption::UserClass(vec![data.to_vec()])
             }
-            if let Some(dest) = p.get_mut(length..length + 2 + option_len) {
-                dest[0] = option.code;
-                dest[1] = option_len as u8;
-                dest[2..].copy_from_slice(&option.data);
+            Err(kind) => return Err(kind),
+        },
+        V_I_VENDOR_CLASS => {
+            DhcpOption::ViVendorClass(decode_enterprise_blocks(data, decode_instances)?)
+        }
+        V_I_VENDOR_SPECIFIC_INFORMATION => DhcpOption::ViVendorSpecificInformation(
+            decode_enterprise_blocks(data, decode_sub_options)?,
+        ),
+        CLIENT_ARCHITECTURE => DhcpOption::ClientArchitecture(all(data, decode_u16s)?),
+        TZ_POSIX_STRING => DhcpOption::TzPosixString(all(data, decode_string)?),
+        TZ_DATABASE_STRING => DhcpOption::TzDatabaseString(all(data, decode_string)?),
+        CLASSLESS_ROUTE_FORMAT => {
+            DhcpOption::ClasslessStaticRoute(all(data, decode_classless_routes)?)
+        }
+        MS_CLASSLESS_STATIC_ROUTE => {
+            DhcpOption::MsClasslessStaticRoute(all(data, decode_classless_routes)?)
+        }
+        _ => match registry.definition(code) {
+            Some(def) => DhcpOption::Custom(
+                code,
+                all(data, |p| decode_option_value(&def.option_type, p))?,
+            ),
+            None => DhcpOption::Unrecognized(RawDhcpOption {
+                code,
+                data: data.to_vec(),
+            }),
+        },
+    })
+}
+
+/// Parses a value of a custom option's type. Types without a fixed length take the rest.
+fn decode_option_value<'a>(
+    option_type: &OptionType,
+    p: &'a [u8],
+) -> IResult<&'a [u8], OptionValue> {
+    let (rest, value) = match option_type {
+        OptionType::Ip => {
+            let (rest, x) = decode_ipv4(p)?;
+            (rest, OptionValue::Ip(x))
+        }
+        OptionType::IpArray => {
+            let (rest, x) = decode_ipv4s(p)?;
+            (rest, OptionValue::IpArray(x))
+        }
+        OptionType::U8 => {
+            let (rest, x) = custom_be_u8(p)?;
+            (rest, OptionValue::U8(x))
+        }
+        OptionType::U16 => {
+            let (rest, x) = custom_be_u16(p)?;
+            (rest, OptionValue::U16(x))
+        }
+        OptionType::U32 => {
+            let (rest, x) = custom_be_u32(p)?;
+            (rest, OptionValue::U32(x))
+        }
+        OptionType::Bool => {
+            let (rest, x) = decode_bool(p)?;
+            (rest, OptionValue::Bool(x))
+        }
+        OptionType::String => {
+            let (rest, x) = decode_string(p)?;
+            (rest, OptionValue::String(x))
+        }
+        OptionType::Bytes => {
+            let (rest, x) = decode_bytes(p)?;
+            (rest, OptionValue::Bytes(x))
+        }
+        OptionType::Record(fields) => {
+            let mut input = p;
+            let mut values = Vec::with_capacity(fields.len());
+            for field in fields {
+                let (rest, value) = decode_option_value(field, input)?;
+                values.push(value);
+                input = rest;
             }
-            length += 2 + option_len;
+            (input, OptionValue::Record(values))
         }
+    };
+    Ok((rest, value))
+}
 
-        if let Some(end_segment) = p.get_mut(length..length + 1) {
-            end_segment[0] = END;
+impl DhcpOption {
+    /// Checks that the option would decode back as itself: lists are not empty and values
+    /// are in the range RFC 2132 allows. `Unrecognized` options are taken as they are.
+    pub fn validate(&self) -> Result<(), DecodeErrorKind> {
+        match self {
+            Self::Unrecognized(_) => return Ok(()),
+            // Checked against the type of its own value, whatever registry defines it.
+            Self::Custom(code, value) => {
+                if *code == PAD || *code == END || title(*code).is_some() {
+                    return Err(DecodeErrorKind::InvalidValue);
+                }
+                let data = value.to_bytes();
+                return match all(&data, |p| decode_option_value(&value.option_type(), p))? {
+                    ref decoded if decoded != value => Err(DecodeErrorKind::InvalidValue),
+                    _ => Ok(()),
+                };
+            }
+            Self::RelayAgentInformation(subs) => {
+                fits_length_octets(subs.iter().map(|sub| sub.to_raw().data.len()))?
+            }
+            Self::UserClass(instances) => fits_length_octets(instances.iter().map(Vec::len))?,
+            Self::ViVendorClass(blocks) => {
+                for (_, classes) in blocks {
+                    fits_length_octets(classes.iter().map(Vec::len))?;
+                    fits_length_octets(Some(classes.iter().map(|c| 1 + c.len()).sum()))?;
+                }
+            }
+            Self::ViVendorSpecificInformation(blocks) => {
+                for (_, subs) in blocks {
+                    fits_length_octets(subs.iter().map(|sub| sub.data.len()))?;
+                    fits_length_octets(Some(subs.iter().map(|sub| 2 + sub.data.len()).sum()))?;
+                }
+            }
+            // An empty label would end the name early and a long one look like a pointer.
+            Self::DomainSearch(names) if !names.iter().all(|name| is_domain_name(name)) => {
+                return Err(DecodeErrorKind::InvalidValue)
+            }
+            _ => {}
         }
-        length += 1;
+        let raw = self.to_raw();
+        match decode_option_data(raw.code, &raw.data)? {
+            // A value too long for a length octet encodes to something else.
+            ref decoded if decoded != self => Err(DecodeErrorKind::InvalidValue),
+            _ => Ok(()),
+        }
+    }
+}
 
-        if let Some(pad_segment) = p.get_mut(length..272) {
-            pad_segment.fill(PAD);
+/// Fails with the first of `lens` that a length octet cannot hold.
+fn fits_length_octets(lens: impl IntoIterator<Item = usize>) -> Result<(), DecodeErrorKind> {
+    match lens.into_iter().find(|&len| len > 255) {
+        Some(len) => Err(DecodeErrorKind::InvalidLength(len)),
+        None => Ok(()),
+    }
+}
+fn custom_take<'a>(n: usize) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+    move |input: &'a [u8]| {
+        if input.len() >= n {
+            Ok((&input[n..], &input[0..n]))
+        } else {
+            Err((input, DecodeErrorKind::Truncated))
         }
+    }
+}
 
-        &p[..length]
+fn decode_cookie(input: &[u8]) -> IResult<&[u8], ()> {
+    match input.strip_prefix(&COOKIE[..]) {
+        Some(rest) => Ok((rest, ())),
+        None => Err((input, DecodeErrorKind::BadCookie)),
     }
 }
+fn custom_be_u8(input: &[u8]) -> IResult<&[u8], u8> {
+    if input.is_empty() {
+        return Err((input, DecodeErrorKind::Truncated));
+    }
 
-const COOKIE: [u8; 4] = [99, 130, 83, 99];
+    Ok((&input[1..], input[0]))
+}
 
-const BOOT_REQUEST: u8 = 1; // From Client;
-const BOOT_REPLY: u8 = 2; // From Server;
+fn custom_be_u16(input: &[u8]) -> IResult<&[u8], u16> {
+    if input.len() < 2 {
+        return Err((input, DecodeErrorKind::Truncated));
+    }
 
-const END: u8 = 255;
-const PAD: u8 = 0;
+    let value = u16::from_be_bytes([input[0], input[1]]);
+    Ok((&input[2..], value))
+}
+fn custom_be_u32(input: &[u8]) -> IResult<&[u8], u32> {
+    if input.len() < 4 {
+        return Err((input, DecodeErrorKind::Truncated));
+    }
+
+    let value = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
+    Ok((&input[4..], value))
+}
+
+/// Position of `at` within `input`, of which it must be a sub-slice.
+fn offset_of(input: &[u8], at: &[u8]) -> usize {
+    at.as_ptr() as usize - input.as_ptr() as usize
+}
+
+/// An option instance as found in the packet: offset of its code byte, code and data.
+type RawOption<'a> = (usize, u8, &'a [u8]);
+
+/// Splits the options of `area` (a part of `packet`) up to the END option.
+fn decode_options<'a>(
+    packet: &'a [u8],
+    area: &'a [u8],
+    mode: DecodeMode,
+) -> Result<Vec<RawOption<'a>>, DecodeError> {
+    let mut options = Vec::new();
+    let mut rest = area;
+
+    while let Some(&code) = rest.first() {
+        match code {
+            PAD => {
+                rest = &rest[1..];
+                continue;
+            }
+            END => break,
+            _ => {}
+        }
+        let offset = offset_of(packet, rest);
+        match decode_raw_option(rest) {
+            Ok((new_rest, (code, data))) => {
+                rest = new_rest;
+                options.push((offset, code, data));
+            }
+            Err(_) if mode == DecodeMode::Lenient => break,
+            Err(_) => {
+                return Err(DecodeError {
+                    offset,
+                    option: Some(code),
+                    kind: DecodeErrorKind::TruncatedOption,
+                })
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+/// Joins all instances of an option into one value (RFC 3396), keeping the
+/// order in which codes first appear and the offset of the first instance.
+fn concatenate_options(options: Vec<RawOption>) -> Vec<(usize, RawDhcpOption)> {
+    let mut merged: Vec<(usize, RawDhcpOption)> = Vec::with_capacity(options.len());
+    for (offset, code, data) in options {
+        match merged.iter_mut().find(|(_, o)| o.code == code) {
+            Some((_, o)) => o.data.extend_from_slice(data),
+            None => merged.push((
+                offset,
+                RawDhcpOption {
+                    code,
+                    data: data.to_vec(),
+                },
+            )),
+        }
+    }
+    merged
+}
+
+/// Splits an option value into the pieces sent as consecutive instances (RFC 3396).
+fn split_option(data: &[u8]) -> Vec<&[u8]> {
+    if data.is_empty() {
+        return vec![data];
+    }
+    data.chunks(MAX_OPTION_LEN).collect()
+}
+
+/// Parses Packet from byte array
+fn decode(
+    packet: &[u8],
+    mode: DecodeMode,
+    registry: &OptionRegistry,
+) -> Result<Packet, DecodeError> {
+    let err = |(at, kind): (&[u8], DecodeErrorKind)| DecodeError {
+        offset: offset_of(packet, at),
+        option: None,
+        kind,
+    };
+    let (options_input, input) = custom_take(236usize)(packet).map_err(|_| DecodeError {
+        offset: packet.len(),
+        option: None,
+        kind: DecodeErrorKind::Truncated,
+    })?;
+
+    let (input, reply) = decode_reply(input).map_err(err)?;
+    let (input, htype) = custom_be_u8(input).map_err(err)?;
+    let (input, hlen) = custom_be_u8(input).map_err(err)?;
+    let (input, hops) = custom_be_u8(input).map_err(err)?;
+    let (input, xid) = custom_be_u32(input).map_err(err)?;
+    let (input, secs) = custom_be_u16(input).map_err(err)?;
+    let (input, flags) = custom_be_u16(input).map_err(err)?;
+    let (input, ciaddr) = decode_ipv4(input).map_err(err)?;
+    let (input, yiaddr) = decode_ipv4(input).map_err(err)?;
+    let (input, siaddr) = decode_ipv4(input).map_err(err)?;
+    let (input, giaddr) = decode_ipv4(input).map_err(err)?;
+
+    let (input, chaddr) = custom_take(MAX_HLEN)(input).map_err(err)?;
+    let chaddr = match chaddr.get(..hlen as usize) {
+        Some(addr) => HardwareAddr::new(htype, addr).unwrap(),
+        None => return Err(err((&packet[2..], DecodeErrorKind::InvalidHlen(hlen)))),
+    };
+    let (input, sname) = custom_take(64usize)(input).map_err(err)?;
+    let (_, file) = custom_take(128usize)(input).map_err(err)?;
+    let mut sname_field = [0; 64];
+    sname_field.copy_from_slice(sname);
+    let mut file_field = [0; 128];
+    file_field.copy_from_slice(file);
+
+    // Without the magic cookie this is a plain BOOTP packet (RFC 951) whose vendor
+    // area has no options we can read. Strict mode only takes one that left the area
+    // empty, as a client without vendor information does.
+    let input = match decode_cookie(options_input) {
+        Ok((input, _)) => input,
+        Err(e) if mode == DecodeMode::Strict && options_input.iter().any(|&b| b != 0) => {
+            return Err(err(e))
+        }
+        Err(_) => {
+            return Ok(Packet {
+                reply,
+                hops,
+                secs,
+                flags,
+                ciaddr,
+                yiaddr,
+                siaddr,
+                giaddr,
+                options: Vec::new(),
+                chaddr,
+                sname: sname_field,
+                file: file_field,
+                vend: Some({
+                    let mut vend = options_input.to_vec();
+                    if vend.len() < 64 {
+                        vend.resize(64, 0);
+                    }
+                    vend
+                }),
+                xid,
+            })
+        }
+    };
+
+    let mut raw = decode_options(packet, input, mode)?;
+
+    // Options that did not fit may continue in the file and then the sname field.
+    // Strict mode rejects an Overload option that is malformed or not in the options area.
+    let invalid_overload = |offset| DecodeError {
+        offset,
+        option: Some(OVERLOAD),
+        kind: DecodeErrorKind::InvalidValue,
+    };
+    let overload = match raw.iter().find(|&&(_, code, _)| code == OVERLOAD) {
+        Some(&(_, _, &[overload @ 1..=3])) => overload,
+        Some(&(offset, _, _)) if mode == DecodeMode::Strict => {
+            return Err(invalid_overload(offset))
+        }
+        _ => 0,
+    };
+    let mut overflow = Vec::new();
+    if overload & OVERLOAD_FILE != 0 {
+        overflow.extend(decode_options(packet, file, mode)?);
+        file_field = [0; 128];
+    }
+    if overload & OVERLOAD_SNAME != 0 {
+        overflow.extend(decode_options(packet, sname, mode)?);
+        sname_field = [0; 64];
+    }
+    match overflow.iter().find(|&&(_, code, _)| code == OVERLOAD) {
+        Some(&(offset, _, _)) if mode == DecodeMode::Strict => {
+            return Err(invalid_overload(offset))
+        }
+        _ => {}
+    }
+    raw.extend(overflow);
+    raw.retain(|&(_, code, _)| code != OVERLOAD);
+
+    let mut options = Vec::new();
+    for (offset, option) in concatenate_options(raw) {
+        match decode_option_data_with(option.code, &option.data, registry) {
+            Ok(option) => options.push(option),
+            Err(_) if mode == DecodeMode::Lenient => options.push(DhcpOption::Unrecognized(option)),
+            Err(kind) => {
+                return Err(DecodeError {
+                    offset,
+                    option: Some(option.code),
+                    kind,
+                })
+            }
+        }
+    }
+
+    Ok(Packet {
+        reply,
+        hops,
+        secs,
+        flags,
+        ciaddr,
+        yiaddr,
+        siaddr,
+        giaddr,
+        options,
+        chaddr,
+        sname: sname_field,
+        file: file_field,
+        vend: None,
+        xid,
+    })
+}
+
+impl Packet {
+    /// Parses a packet, keeping malformed options as raw bytes (see `DecodeMode::Lenient`).
+    pub fn from(input: &[u8]) -> Result<Packet, DecodeError> {
+        decode(input, DecodeMode::Lenient, &NO_DEFINITIONS)
+    }
+
+    /// Parses a packet, handling malformed options as `mode` says.
+    pub fn decode(input: &[u8], mode: DecodeMode) -> Result<Packet, DecodeError> {
+        decode(input, mode, &NO_DEFINITIONS)
+    }
+
+    /// Like `decode`, with custom options decoded as `registry` defines them.
+    pub fn decode_with(
+        input: &[u8],
+        mode: DecodeMode,
+        registry: &OptionRegistry,
+    ) -> Result<Packet, DecodeError> {
+        decode(input, mode, registry)
+    }
+
+    /// Extracts requested option payload from packet if available
+    pub fn option(&self, code: u8) -> Option<&DhcpOption> {
+        self.options.iter().find(|&option| option.code() == code)
+    }
+
+    /// Convenience function for extracting a packet's message type.
+    pub fn message_type(&self) -> Result<MessageType, String> {
+        match self.option(DHCP_MESSAGE_TYPE) {
+            Some(DhcpOption::DhcpMessageType(msgtype)) => Ok(*msgtype),
+            Some(option) => Err(format![
+                "Got wrong enum code {} for DHCP_MESSAGE_TYPE",
+                option.code()
+            ]),
+            None => Err("Packet does not have MessageType option".to_string()),
+        }
+    }
+    /// A packet without a DHCP message type is a BOOTP message (RFC 1542).
+    pub fn is_bootp(&self) -> bool {
+        self.option(DHCP_MESSAGE_TYPE).is_none()
+    }
+
+    /// All options with `code`. Decoding joins the instances of an option into one
+    /// (RFC 3396), so this finds more than `option` does only in a packet built by hand.
+    pub fn options_with_code(&self, code: u8) -> impl Iterator<Item = &DhcpOption> {
+        self.options
+            .iter()
+            .filter(move |option| option.code() == code)
+    }
+
+    /// Puts `option` in place of the first option with its code, dropping any others with
+    /// that code. A new option is added last, or first if it is the DHCP message type.
+    pub fn set_option(&mut self, option: DhcpOption) {
+        let code = option.code();
+        match self.options.iter().position(|o| o.code() == code) {
+            Some(i) => {
+                self.options[i] = option;
+                let mut seen = 0;
+                self.options.retain(|o| {
+                    seen += (o.code() == code) as usize;
+                    o.code() != code || seen == 1
+                });
+            }
+            None if code == DHCP_MESSAGE_TYPE => self.options.insert(0, option),
+            None => self.options.push(option),
+        }
+    }
+
+    /// Removes every option with `code`, returning the first.
+    pub fn remove_option(&mut self, code: u8) -> Option<DhcpOption> {
+        let i = self.options.iter().position(|o| o.code() == code)?;
+        let removed = self.options.remove(i);
+        self.options.retain(|o| o.code() != code);
+        Some(removed)
+    }
+
+    pub fn requested_ip(&self) -> Option<Ipv4Addr> {
+        match self.option(REQUESTED_IP_ADDRESS) {
+            Some(DhcpOption::RequestedIpAddress(addr)) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    pub fn set_requested_ip(&mut self, addr: Ipv4Addr) {
+        self.set_option(DhcpOption::RequestedIpAddress(addr));
+    }
+
+    /// Lease time in seconds, `u32::MAX` meaning infinite.
+    pub fn lease_time(&self) -> Option<u32> {
+        match self.option(IP_ADDRESS_LEASE_TIME) {
+            Some(DhcpOption::IpAddressLeaseTime(secs)) => Some(*secs),
+            _ => None,
+        }
+    }
+
+    pub fn set_lease_time(&mut self, secs: u32) {
+        self.set_option(DhcpOption::IpAddressLeaseTime(secs));
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        match self.option(HOST_NAME) {
+            Some(DhcpOption::HostName(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn set_hostname(&mut self, name: &str) {
+        self.set_option(DhcpOption::HostName(name.to_string()));
+    }
+
+    pub fn client_id(&self) -> Option<&ClientIdentifier> {
+        match self.option(CLIENT_IDENTIFIER) {
+            Some(DhcpOption::ClientIdentifier(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn set_client_id(&mut self, id: ClientIdentifier) {
+        self.set_option(DhcpOption::ClientIdentifier(id));
+    }
+
+    pub fn parameter_request_list(&self) -> Option<&[u8]> {
+        match self.option(PARAMETER_REQUEST_LIST) {
+            Some(DhcpOption::ParameterRequestList(prl)) => Some(prl),
+            _ => None,
+        }
+    }
+
+    pub fn set_parameter_request_list(&mut self, codes: Vec<u8>) {
+        self.set_option(DhcpOption::ParameterRequestList(codes));
+    }
+
+    pub fn server_identifier(&self) -> Option<Ipv4Addr> {
+        match self.option(SERVER_IDENTIFIER) {
+            Some(DhcpOption::ServerIdentifier(addr)) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    pub fn set_server_identifier(&mut self, addr: Ipv4Addr) {
+        self.set_option(DhcpOption::ServerIdentifier(addr));
+    }
+
+    /// Vendor class identifier (option 60), such as "PXEClient:Arch:00007".
+    pub fn vendor_class(&self) -> Option<&[u8]> {
+        match self.option(VENDOR_CLASS_IDENTIFIER) {
+            Some(DhcpOption::VendorClassIdentifier(class)) => Some(class),
+            _ => None,
+        }
+    }
+
+    pub fn set_vendor_class(&mut self, class: &[u8]) {
+        self.set_option(DhcpOption::VendorClassIdentifier(class.to_vec()));
+    }
+
+    /// Whether the client listed `code` in its Parameter Request List.
+    pub fn requests_option(&self, code: u8) -> bool {
+        self.parameter_request_list()
+            .is_some_and(|prl| prl.contains(&code))
+    }
+
+    /// Whether the client accepts FORCERENEW authenticated with a nonce (RFC 6704).
+    pub fn forcerenew_nonce_capable(&self) -> bool {
+        match self.option(FORCERENEW_NONCE_CAPABLE) {
+            Some(DhcpOption::ForcerenewNonceCapable(algorithms)) => {
+                algorithms.contains(&AUTH_ALGORITHM_HMAC_MD5)
+            }
+            _ => false,
+        }
+    }
+
+    /// Key to look up the client's lease by.
+    pub fn client_key(&self) -> ClientKey {
+        match self.client_id() {
+            Some(id) => ClientKey::ClientIdentifier(id.clone()),
+            None => ClientKey::HardwareAddr(self.chaddr),
+        }
+    }
+
+    /// Sub-options a relay agent added to the request (option 82).
+    pub fn relay_agent_information(&self) -> Option<&[RelayAgentSubOption]> {
+        match self.option(RELAY_AGENT_INFORMATION) {
+            Some(DhcpOption::RelayAgentInformation(subs)) => Some(subs),
+            _ => None,
+        }
+    }
+
+    fn relay_sub_option(&self, code: u8) -> Option<&RelayAgentSubOption> {
+        self.relay_agent_information()?
+            .iter()
+            .find(|s| s.code() == code)
+    }
+
+    pub fn circuit_id(&self) -> Option<&[u8]> {
+        match self.relay_sub_option(AGENT_CIRCUIT_ID) {
+            Some(RelayAgentSubOption::CircuitId(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn remote_id(&self) -> Option<&[u8]> {
+        match self.relay_sub_option(AGENT_REMOTE_ID) {
+            Some(RelayAgentSubOption::RemoteId(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn link_selection(&self) -> Option<Ipv4Addr> {
+        match self.relay_sub_option(LINK_SELECTION) {
+            Some(RelayAgentSubOption::LinkSelection(addr)) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    pub fn subscriber_id(&self) -> Option<&str> {
+        match self.relay_sub_option(SUBSCRIBER_ID) {
+            Some(RelayAgentSubOption::SubscriberId(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn server_identifier_override(&self) -> Option<Ipv4Addr> {
+        match self.relay_sub_option(SERVER_IDENTIFIER_OVERRIDE) {
+            Some(RelayAgentSubOption::ServerIdentifierOverride(addr)) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// Whether the client asked for replies to be broadcast.
+    pub fn broadcast(&self) -> bool {
+        self.flags & BROADCAST_FLAG == BROADCAST_FLAG
+    }
+
+    /// Sets or clears the broadcast bit, leaving the other flag bits untouched.
+    pub fn set_broadcast(&mut self, broadcast: bool) {
+        if broadcast {
+            self.flags |= BROADCAST_FLAG;
+        } else {
+            self.flags &= !BROADCAST_FLAG;
+        }
+    }
+
+    /// Largest DHCP message the sender will accept (option 57), never less than
+    /// the RFC 2131 minimum of 576 octets.
+    pub fn max_message_size(&self) -> u16 {
+        match self.option(MAXIMUM_DHCP_MESSAGE_SIZE) {
+            Some(DhcpOption::MaximumDhcpMessageSize(size)) => (*size).max(MIN_MAX_MESSAGE_SIZE),
+            _ => MIN_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Encodes the packet for a receiver that accepts the minimum message size,
+    /// leaving out any options that are invalid or do not fit. Use `encode_with_limit`
+    /// to find out which options were dropped.
+    pub fn encode<'a>(&self, p: &'a mut [u8]) -> &'a [u8] {
+        let length = match self.encode_with_limit(p, MIN_MAX_MESSAGE_SIZE) {
+            Ok(encoded) => encoded.len(),
+            Err(e) => e.len,
+        };
+        &p[..length]
+    }
+
+    /// Encodes the packet so that, including IP and UDP headers, it is no larger
+    /// than `max_message_size` (as sent by the client in option 57) or the buffer.
+    /// BOOTP packets may be limited to the 300 octet BOOTP message, while DHCP ones
+    /// always get the RFC 2131 minimum, and only DHCP packets overload sname and file.
+    /// Packets are padded to at least 300 octets for the sake of BOOTP clients and relays.
+    ///
+    /// If some options do not fit, the packet is still encoded without them into
+    /// the first `len` bytes of `p`, and the error lists their codes. That includes
+    /// all options of a packet with a `vend` area, which has no room for them.
+    /// Options that would not decode back as themselves (see `DhcpOption::validate`),
+    /// such as a value too long for a nested length octet, are left out the same way.
+    pub fn encode_with_limit<'a>(
+        &self,
+        p: &'a mut [u8],
+        max_message_size: u16,
+    ) -> Result<&'a [u8], OptionsDropped> {
+        let bootp = self.is_bootp();
+        let floor = if bootp {
+            BOOTP_MIN_LEN
+        } else {
+            MIN_MAX_MESSAGE_SIZE as usize - IP_UDP_HEADER_LEN
+        };
+        let limit = (max_message_size as usize)
+            .saturating_sub(IP_UDP_HEADER_LEN)
+            .max(floor)
+            .min(p.len());
+        let mut length = 240;
+        let mut dropped = Vec::new();
+
+        p[..12].copy_from_slice(&[
+            if self.reply { BOOT_REPLY } else { BOOT_REQUEST },
+            self.chaddr.htype,
+            self.chaddr.len,
+            self.hops,
+            ((self.xid >> 24) & 0xFF) as u8,
+            ((self.xid >> 16) & 0xFF) as u8,
+            ((self.xid >> 8) & 0xFF) as u8,
+            (self.xid & 0xFF) as u8,
+            (self.secs >> 8) as u8,
+            (self.secs & 255) as u8,
+            (self.flags >> 8) as u8,
+            (self.flags & 255) as u8,
+        ]);
+
+        p[12..16].copy_from_slice(&self.ciaddr.octets());
+        p[16..20].copy_from_slice(&self.yiaddr.octets());
+        p[20..24].copy_from_slice(&self.siaddr.octets());
+        p[24..28].copy_from_slice(&self.giaddr.octets());
+        p[28..44].copy_from_slice(&self.chaddr.addr);
+        p[44..108].copy_from_slice(&self.sname);
+        p[108..236].copy_from_slice(&self.file);
+
+        if let Some(vend) = &self.vend {
+            let length = (236 + vend.len()).min(limit);
+            p[236..length].copy_from_slice(&vend[..length - 236]);
+            let length = pad_to_bootp_min(p, length);
+            if !self.options.is_empty() {
+                return Err(OptionsDropped {
+                    len: length,
+                    invalid: Vec::new(),
+                    codes: self.options.iter().map(|o| o.code()).collect(),
+                });
+            }
+            return Ok(&p[..length]);
+        }
+        p[236..240].copy_from_slice(&COOKIE);
+
+        let mut invalid = Vec::new();
+        let options: Vec<RawDhcpOption> = self
+            .options
+            .iter()
+            .filter(|o| o.code() != OVERLOAD)
+            .filter(|o| match o.validate() {
+                Ok(()) => true,
+                Err(kind) => {
+                    invalid.push((o.code(), kind));
+                    false
+                }
+            })
+            .map(|o| o.to_raw())
+            .collect();
+
+        // Each area is (next free byte, end); every area keeps one byte back for END.
+        let mut areas = vec![(length, limit)];
+        let needed: usize = options
+            .iter()
+            .map(|o| 2 * split_option(&o.data).len() + o.data.len())
+            .sum();
+        if length + needed >= limit && !bootp {
+            let mut overload = 0;
+            if self.file.iter().all(|&b| b == 0) {
+                areas.push((108, 236));
+                overload |= OVERLOAD_FILE;
+            }
+            if self.sname.iter().all(|&b| b == 0) {
+                areas.push((44, 108));
+                overload |= OVERLOAD_SNAME;
+            }
+            if overload != 0 {
+                p[length..length + 3].copy_from_slice(&[OVERLOAD, 1, overload]);
+                areas[0].0 += 3;
+            }
+        }
+
+        // Fill the areas in order so that the receiver sees the options as they were
+        // given; an option is left out entirely if any of its pieces cannot be placed.
+        let mut area = 0;
+        for option in &options {
+            let pieces = split_option(&option.data);
+            let mut placed = areas.clone();
+            let mut at = area;
+            let mut positions = Vec::with_capacity(pieces.len());
+            for piece in &pieces {
+                let fits = |&(pos, end): &(usize, usize)| pos + 2 + piece.len() < end;
+                match placed[at..].iter().position(fits) {
+                    Some(i) => at += i,
+                    None => break,
+                }
+                positions.push(placed[at].0);
+                placed[at].0 += 2 + piece.len();
+            }
+            if positions.len() != pieces.len() {
+                dropped.push(option.code);
+                continue;
+            }
+
+            for (piece, pos) in pieces.iter().zip(positions) {
+                if let Some(dest) = p.get_mut(pos..pos + 2 + piece.len()) {
+                    dest[0] = option.code;
+                    dest[1] = piece.len() as u8;
+                    dest[2..].copy_from_slice(piece);
+                }
+            }
+            areas = placed;
+            area = at;
+        }
+
+        for &(pos, end) in &areas[1..] {
+            p[pos] = END;
+            p[pos + 1..end].fill(PAD);
+        }
+        length = areas[0].0;
+
+        if let Some(end_segment) = p.get_mut(length..length + 1) {
+            end_segment[0] = END;
+        }
+        length += 1;
+
+        let length = pad_to_bootp_min(p, length);
+
+        if !dropped.is_empty() || !invalid.is_empty() {
+            return Err(OptionsDropped {
+                len: length,
+                invalid,
+                codes: dropped,
+            });
+        }
+        Ok(&p[..length])
+    }
+}
+
+/// Pads the encoded packet to the minimum BOOTP message size, if the buffer allows.
+fn pad_to_bootp_min(p: &mut [u8], length: usize) -> usize {
+    let padded = length.max(BOOTP_MIN_LEN).min(p.len()).max(length);
+    p[length..padded].fill(PAD);
+    padded
+}
+
+/// Options left out by `Packet::encode_with_limit`.
+#[derive(Debug, PartialEq)]
+pub struct OptionsDropped {
+    /// Length of the packet that was encoded without them.
+    pub len: usize,
+    /// Options that would not decode back as themselves, and why.
+    pub invalid: Vec<(u8, DecodeErrorKind)>,
+    /// Options that did not fit.
+    pub codes: Vec<u8>,
+}
+
+impl fmt::Display for OptionsDropped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("encoded without")?;
+        for (code, kind) in &self.invalid {
+            write!(f, " invalid option {} ({})", code, kind)?;
+        }
+        if !self.codes.is_empty() {
+            write!(f, " options {:?} that did not fit", self.codes)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for OptionsDropped {}
+
+/// Borrowed, read-only view of a packet in a receive buffer.
+///
+/// Only the fixed header and the magic cookie are checked up front. Options are
+/// parsed as they are read, leniently and without building a `Vec` of them, so
+/// handlers that only look at a few fields avoid the allocations of `Packet::from`.
+#[derive(Copy, Clone, Debug)]
+pub struct PacketView<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> PacketView<'a> {
+    pub fn new(buf: &'a [u8]) -> Result<PacketView<'a>, DecodeError> {
+        let err = |offset, kind| DecodeError {
+            offset,
+            option: None,
+            kind,
+        };
+        if buf.len() < 236 {
+            return Err(err(buf.len(), DecodeErrorKind::Truncated));
+        }
+        if buf[0] != BOOT_REQUEST && buf[0] != BOOT_REPLY {
+            return Err(err(0, DecodeErrorKind::InvalidOp(buf[0])));
+        }
+        if buf[2] as usize > MAX_HLEN {
+            return Err(err(2, DecodeErrorKind::InvalidHlen(buf[2])));
+        }
+        Ok(PacketView { buf })
+    }
+
+    /// Decodes the whole packet into an owned `Packet`.
+    pub fn to_packet(&self) -> Result<Packet, DecodeError> {
+        Packet::from(self.buf)
+    }
+
+    pub fn reply(&self) -> bool {
+        self.buf[0] == BOOT_REPLY
+    }
+
+    pub fn hops(&self) -> u8 {
+        self.buf[3]
+    }
+
+    pub fn xid(&self) -> u32 {
+        u32::from_be_bytes([self.buf[4], self.buf[5], self.buf[6], self.buf[7]])
+    }
+
+    pub fn secs(&self) -> u16 {
+        u16::from_be_bytes([self.buf[8], self.buf[9]])
+    }
+
+    pub fn flags(&self) -> u16 {
+        u16::from_be_bytes([self.buf[10], self.buf[11]])
+    }
+
+    pub fn broadcast(&self) -> bool {
+        self.flags() & BROADCAST_FLAG == BROADCAST_FLAG
+    }
+
+    pub fn ciaddr(&self) -> Ipv4Addr {
+        self.ipv4(12)
+    }
+
+    pub fn yiaddr(&self) -> Ipv4Addr {
+        self.ipv4(16)
+    }
+
+    pub fn siaddr(&self) -> Ipv4Addr {
+        self.ipv4(20)
+    }
+
+    pub fn giaddr(&self) -> Ipv4Addr {
+        self.ipv4(24)
+    }
+
+    pub fn chaddr(&self) -> HardwareAddr {
+        HardwareAddr::new(self.buf[1], &self.buf[28..28 + self.buf[2] as usize]).unwrap()
+    }
+
+    /// The raw sname field, which holds options instead if the packet overloads it.
+    pub fn sname(&self) -> &'a [u8] {
+        &self.buf[44..108]
+    }
+
+    /// The raw file field, which holds options instead if the packet overloads it.
+    pub fn file(&self) -> &'a [u8] {
+        &self.buf[108..236]
+    }
+
+    /// Vendor area of a BOOTP packet without the magic cookie, which has no options.
+    pub fn vend(&self) -> Option<&'a [u8]> {
+        match self.buf.get(236..240) {
+            Some(cookie) if cookie == COOKIE => None,
+            _ => Some(&self.buf[236..]),
+        }
+    }
+
+    /// Iterates over the option instances in the order they appear, following
+    /// option overload into the file and sname fields. Instances of the same code
+    /// are not joined; use `option_data` for that. Parsing stops at an option
+    /// that runs past the end of its area.
+    pub fn raw_options(&self) -> impl Iterator<Item = (u8, &'a [u8])> + 'a {
+        let options = match self.vend() {
+            Some(_) => &[],
+            None => &self.buf[240..],
+        };
+        let overload = match RawOptions::new(options).find(|&(code, _)| code == OVERLOAD) {
+            Some((_, [overload])) => *overload,
+            _ => 0,
+        };
+        let mut iter = RawOptions::new(options);
+        if overload & OVERLOAD_FILE != 0 {
+            iter.file = self.file();
+        }
+        if overload & OVERLOAD_SNAME != 0 {
+            iter.sname = self.sname();
+        }
+        iter.filter(|&(code, _)| code != OVERLOAD)
+    }
+
+    /// Data of an option, with all its instances joined (RFC 3396). Only allocates
+    /// if the option is split over several instances.
+    pub fn option_data(&self, code: u8) -> Option<Cow<'a, [u8]>> {
+        let mut data: Option<Cow<'a, [u8]>> = None;
+        for (_, more) in self.raw_options().filter(|&(c, _)| c == code) {
+            match data.as_mut() {
+                Some(data) => data.to_mut().extend_from_slice(more),
+                None => data = Some(Cow::Borrowed(more)),
+            }
+        }
+        data
+    }
+
+    /// Decodes a single option, keeping it as `DhcpOption::Unrecognized` if it is malformed.
+    pub fn option(&self, code: u8) -> Option<DhcpOption> {
+        self.option_with(code, &NO_DEFINITIONS)
+    }
+
+    /// Like `option`, with a custom option decoded as `registry` defines it.
+    pub fn option_with(&self, code: u8, registry: &OptionRegistry) -> Option<DhcpOption> {
+        let data = self.option_data(code)?;
+        Some(match decode_option_data_with(code, &data, registry) {
+            Ok(option) => option,
+            Err(_) => DhcpOption::Unrecognized(RawDhcpOption {
+                code,
+                data: data.into_owned(),
+            }),
+        })
+    }
+
+    pub fn message_type(&self) -> Option<MessageType> {
+        match self.option_data(DHCP_MESSAGE_TYPE)?.as_ref() {
+            [mtype] => MessageType::try_from(*mtype).ok(),
+            _ => None,
+        }
+    }
+
+    fn ipv4(&self, at: usize) -> Ipv4Addr {
+        Ipv4Addr::new(
+            self.buf[at],
+            self.buf[at + 1],
+            self.buf[at + 2],
+            self.buf[at + 3],
+        )
+    }
+}
+
+/// Lenient option scanner behind `PacketView::raw_options`, which moves on to
+/// the file and then the sname field when those hold overloaded options.
+struct RawOptions<'a> {
+    area: &'a [u8],
+    file: &'a [u8],
+    sname: &'a [u8],
+}
+
+impl<'a> RawOptions<'a> {
+    fn new(area: &'a [u8]) -> RawOptions<'a> {
+        RawOptions {
+            area,
+            file: &[],
+            sname: &[],
+        }
+    }
+}
+
+impl<'a> Iterator for RawOptions<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.area.first() {
+                Some(&PAD) => self.area = &self.area[1..],
+                Some(&END) | None => {
+                    if !self.file.is_empty() {
+                        self.area = std::mem::take(&mut self.file);
+                    } else if !self.sname.is_empty() {
+                        self.area = std::mem::take(&mut self.sname);
+                    } else {
+                        return None;
+                    }
+                }
+                Some(_) => match decode_raw_option(self.area) {
+                    Ok((rest, option)) => {
+                        self.area = rest;
+                        return Some(option);
+                    }
+                    Err(_) => self.area = &[],
+                },
+            }
+        }
+    }
+}
+
+const COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+const BOOT_REQUEST: u8 = 1; // From Client;
+const BOOT_REPLY: u8 = 2; // From Server;
+
+/// Smallest message every DHCP client must accept, including IP and UDP headers (RFC 2131).
+pub const MIN_MAX_MESSAGE_SIZE: u16 = 576;
+
+/// IPv4 header without options plus UDP header, counted in option 57.
+const IP_UDP_HEADER_LEN: usize = 28;
+
+/// Largest value a single option instance can carry.
+const MAX_OPTION_LEN: usize = 255;
+
+/// Smallest MTU an interface can have (RFC 2132, section 5.1).
+const MIN_MTU: u16 = 68;
+
+/// Size of a BOOTP message with its 64 byte vendor extension area (RFC 951).
+const BOOTP_MIN_LEN: usize = 300;
+
+// Values of the OVERLOAD option.
+const OVERLOAD_FILE: u8 = 1;
+const OVERLOAD_SNAME: u8 = 2;
+
+const END: u8 = 255;
+const PAD: u8 = 0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a captured packet: the first 44 header bytes (up to and including
+    /// chaddr), sname/file as captured, then the cookie and options area.
+    fn capture(header: &[u8; 44], sname: &[u8], file: &[u8], options: &[u8]) -> Vec<u8> {
+        let mut p = header.to_vec();
+        p.extend_from_slice(sname);
+        p.resize(108, 0);
+        p.extend_from_slice(file);
+        p.resize(236, 0);
+        p.extend_from_slice(&COOKIE);
+        p.extend_from_slice(options);
+        if p.len() < 300 {
+            p.resize(300, PAD);
+        }
+        p
+    }
+
+    const CLIENT_HEADER: [u8; 44] = [
+        1, 1, 6, 0, 0x00, 0x00, 0x3d, 0x1d, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    const SERVER_HEADER: [u8; 44] = [
+        2, 1, 6, 0, 0x00, 0x00, 0x3d, 0x1e, 0, 0, 0, 0, 0, 0, 0, 0, 192, 168, 0, 10, 192, 168, 0,
+        1, 0, 0, 0, 0, 0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    fn discover() -> Vec<u8> {
+        capture(
+            &CLIENT_HEADER,
+            &[],
+            &[],
+            &[
+                53, 1, 1, // DHCPDISCOVER
+                61, 7, 1, 0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42, // Client identifier
+                50, 4, 0, 0, 0, 0, // Requested IP address
+                55, 4, 1, 3, 6, 42, // Parameter request list
+                255,
+            ],
+        )
+    }
+
+    fn request() -> Vec<u8> {
+        let mut header = CLIENT_HEADER;
+        header[7] = 0x1e;
+        capture(
+            &header,
+            &[],
+            &[],
+            &[
+                53, 1, 3, // DHCPREQUEST
+                61, 7, 1, 0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42, // Client identifier
+                50, 4, 192, 168, 0, 10, // Requested IP address
+                54, 4, 192, 168, 0, 1, // Server identifier
+                55, 4, 1, 3, 6, 42, // Parameter request list
+                255,
+            ],
+        )
+    }
+
+    fn ack() -> Vec<u8> {
+        capture(
+            &SERVER_HEADER,
+            &[],
+            &[],
+            &[
+                53, 1, 5, // DHCPACK
+                58, 4, 0, 0, 0x07, 0x08, // Renewal time
+                59, 4, 0, 0, 0x0c, 0x4e, // Rebinding time
+                51, 4, 0, 0, 0x0e, 0x10, // Lease time
+                54, 4, 192, 168, 0, 1, // Server identifier
+                1, 4, 255, 255, 255, 0, // Subnet mask
+                255,
+            ],
+        )
+    }
+
+    fn assert_round_trip(captured: &[u8]) -> Packet {
+        let packet = Packet::from(captured).unwrap();
+        let mut buf = [0; 1500];
+        let encoded = packet.encode(&mut buf);
+        assert_eq!(encoded, &captured[..encoded.len()]);
+        assert_eq!(Packet::from(encoded).unwrap(), packet);
+        packet
+    }
+
+    #[test]
+    fn discover_round_trip() {
+        let p = assert_round_trip(&discover());
+        assert!(!p.reply);
+        assert_eq!(p.xid, 0x3d1d);
+        assert_eq!(
+            p.chaddr,
+            HardwareAddr::ethernet([0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42])
+        );
+        assert_eq!(p.message_type().unwrap(), MessageType::Discover);
+    }
+
+    #[test]
+    fn request_round_trip() {
+        let p = assert_round_trip(&request());
+        assert_eq!(p.message_type().unwrap(), MessageType::Request);
+        assert_eq!(
+            p.option(REQUESTED_IP_ADDRESS),
+            Some(&DhcpOption::RequestedIpAddress(Ipv4Addr::new(
+                192, 168, 0, 10
+            )))
+        );
+    }
+
+    #[test]
+    fn ack_round_trip() {
+        let p = assert_round_trip(&ack());
+        assert!(p.reply);
+        assert_eq!(p.yiaddr, Ipv4Addr::new(192, 168, 0, 10));
+        assert_eq!(p.siaddr, Ipv4Addr::new(192, 168, 0, 1));
+        assert_eq!(p.options.len(), 6);
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let mut captured = discover();
+        captured[10] = 0x80;
+        let p = assert_round_trip(&captured);
+        assert!(p.broadcast());
+        assert_eq!(p.flags, BROADCAST_FLAG);
+
+        // Reserved bits must survive as well, even though they should be zero.
+        captured[11] = 0x01;
+        let p = assert_round_trip(&captured);
+        assert_eq!(p.flags, 0x8001);
+    }
+
+    #[test]
+    fn sname_and_file_round_trip() {
+        let captured = capture(
+            &SERVER_HEADER,
+            b"tftp.example.com",
+            b"pxelinux.0",
+            &[53, 1, 2, 255],
+        );
+        let p = assert_round_trip(&captured);
+        assert_eq!(&p.sname[..17], b"tftp.example.com\0");
+        assert_eq!(&p.file[..11], b"pxelinux.0\0");
+    }
+
+    #[test]
+    fn variable_length_chaddr() {
+        let mut header = CLIENT_HEADER;
+        header[1] = HTYPE_IEEE802;
+        header[2] = 16;
+        for (i, b) in header[28..44].iter_mut().enumerate() {
+            *b = i as u8 + 1;
+        }
+        let p = assert_round_trip(&capture(&header, &[], &[], &[53, 1, 1, 255]));
+        assert_eq!(p.chaddr.htype(), HTYPE_IEEE802);
+        assert_eq!(p.chaddr.len(), 16);
+        assert_eq!(p.chaddr.as_bytes()[15], 16);
+
+        header[1] = HTYPE_INFINIBAND;
+        header[2] = 0;
+        header[28..44].fill(0);
+        let p = assert_round_trip(&capture(&header, &[], &[], &[53, 1, 1, 255]));
+        assert!(p.chaddr.is_empty());
+
+        header[2] = 17;
+        assert!(Packet::from(&capture(&header, &[], &[], &[53, 1, 1, 255])).is_err());
+    }
+
+    #[test]
+    fn hardware_addr_display() {
+        let mac = HardwareAddr::ethernet([0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42]);
+        assert_eq!(mac.to_string(), "00:0b:82:01:fc:42");
+        assert_eq!(
+            HardwareAddr::new(HTYPE_INFINIBAND, &[])
+                .unwrap()
+                .to_string(),
+            ""
+        );
+        assert!(HardwareAddr::new(HTYPE_IEEE802, &[0; 17]).is_none());
+    }
+
+    #[test]
+    fn overloaded_options_are_merged() {
+        let mut sname = vec![3, 4, 192, 168, 0, 1, 255];
+        sname.resize(64, 0);
+        let captured = capture(
+            &SERVER_HEADER,
+            &sname,
+            &[1, 4, 255, 255, 255, 0, 255],
+            &[53, 1, 5, 52, 1, 3, 255],
+        );
+        let p = Packet::from(&captured).unwrap();
+        assert_eq!(
+            p.options,
+            vec![
+                DhcpOption::DhcpMessageType(MessageType::Ack),
+                DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+                DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 1)]),
+            ]
+        );
+        assert_eq!(p.sname, [0; 64]);
+        assert_eq!(p.file, [0; 128]);
+    }
+
+    #[test]
+    fn invalid_options_are_left_out() {
+        let p = crate::builder::PacketBuilder::new(HardwareAddr::ethernet([0; 6]))
+            .option(DhcpOption::Router(vec![]))
+            .option(DhcpOption::HostName("h".to_string()))
+            .option(DhcpOption::UserClass(vec![vec![b'a'; 256]]))
+            .build();
+        let mut buf = [0; 1500];
+        let e = p.encode_with_limit(&mut buf, 1500).unwrap_err();
+        assert_eq!(e.codes, Vec::<u8>::new());
+        let codes: Vec<u8> = e.invalid.iter().map(|&(code, _)| code).collect();
+        assert_eq!(codes, vec![ROUTER, USER_CLASS]);
+
+        let decoded = Packet::from(p.encode(&mut buf)).unwrap();
+        assert_eq!(decoded.options, vec![DhcpOption::HostName("h".to_string())]);
+    }
+
+    #[test]
+    fn malformed_overload_in_strict_mode() {
+        fn error(captured: &[u8]) -> Option<(usize, Option<u8>, DecodeErrorKind)> {
+            let e = Packet::decode(captured, DecodeMode::Strict).err()?;
+            Some((e.offset, e.option, e.kind))
+        }
+        let invalid = |offset| Some((offset, Some(OVERLOAD), DecodeErrorKind::InvalidValue));
+
+        let captured = capture(&SERVER_HEADER, &[], &[], &[53, 1, 5, 52, 1, 4, 255]);
+        assert_eq!(error(&captured), invalid(243));
+        assert!(Packet::from(&captured).is_ok());
+
+        let captured = capture(&SERVER_HEADER, &[], &[], &[53, 1, 5, 52, 2, 1, 0, 255]);
+        assert_eq!(error(&captured), invalid(243));
+
+        // Overload may only appear in the options area itself.
+        let captured = capture(
+            &SERVER_HEADER,
+            &[],
+            &[52, 1, 2, 255],
+            &[53, 1, 5, 52, 1, 1, 255],
+        );
+        assert_eq!(error(&captured), invalid(108));
+        let p = Packet::from(&captured).unwrap();
+        assert_eq!(p.sname, [0; 64]);
+        assert_eq!(p.options.len(), 1);
+
+        let captured = capture(
+            &SERVER_HEADER,
+            &[],
+            &[1, 4, 255, 0, 0, 0, 255],
+            &[52, 1, 1, 255],
+        );
+        assert_eq!(error(&captured), None);
+    }
+
+    #[test]
+    fn options_spill_into_unused_fields() {
+        let mut p = Packet::from(&ack()).unwrap();
+        p.options.push(DhcpOption::Message("m".repeat(255)));
+        p.options.push(DhcpOption::HostName("h".repeat(100)));
+        p.options.push(DhcpOption::Unrecognized(RawDhcpOption {
+            code: 224,
+            data: vec![1; 50],
+        }));
+
+        let mut buf = [0; 1500];
+        let encoded = p.encode(&mut buf);
+        assert_eq!(&encoded[240..243], &[OVERLOAD, 1, 3]);
+        assert_eq!(&encoded[108..110], &[HOST_NAME, 100]);
+        assert_eq!(&encoded[44..46], &[224, 50]);
+        assert_eq!(Packet::from(encoded).unwrap(), p);
+
+        // A boot file name in use leaves only sname for the overflow, so the
+        // host name no longer fits anywhere and is left out.
+        p.file[..10].copy_from_slice(b"pxelinux.0");
+        let len = match p.encode_with_limit(&mut buf, MIN_MAX_MESSAGE_SIZE) {
+            Err(e) => {
+                assert_eq!(e.codes, vec![HOST_NAME]);
+                e.len
+            }
+            Ok(_) => panic!("host name should not fit"),
+        };
+        assert_eq!(&buf[240..243], &[OVERLOAD, 1, 2]);
+        let decoded = Packet::from(&buf[..len]).unwrap();
+        assert_eq!(decoded.file, p.file);
+        assert_eq!(decoded.option(HOST_NAME), None);
+        assert_eq!(decoded.option(224), p.option(224));
+    }
+
+    #[test]
+    fn split_options_are_concatenated() {
+        let mut options = vec![53, 1, 5, 3, 4, 10, 0, 0, 1, 6, 4, 10, 0, 0, 53];
+        let mut long = vec![224, 255];
+        long.extend((0..255).map(|i| i as u8));
+        options.extend(&long);
+        options.extend(&[224, 45]);
+        options.extend((0..45).map(|i| i as u8));
+        options.extend(&[52, 1, 1, 3, 4, 10, 0, 0, 2, 255]);
+        let captured = capture(&SERVER_HEADER, &[], &[3, 4, 10, 0, 0, 3, 255], &options);
+
+        let p = Packet::from(&captured).unwrap();
+        assert_eq!(
+            p.option(ROUTER),
+            Some(&DhcpOption::Router(vec![
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2),
+                Ipv4Addr::new(10, 0, 0, 3),
+            ]))
+        );
+        match p.option(224) {
+            Some(DhcpOption::Unrecognized(raw)) => {
+                assert_eq!(raw.data.len(), 300);
+                assert_eq!(raw.data[299], 44);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(p.options.len(), 4);
+    }
+
+    #[test]
+    fn long_options_are_split() {
+        let data = [7; 300];
+        let pieces = split_option(&data);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].len(), 255);
+        assert_eq!(pieces[1].len(), 45);
+        assert_eq!(split_option(&[]), vec![&[] as &[u8]]);
+    }
+
+    #[test]
+    fn long_options_round_trip() {
+        let mut p = Packet::from(&ack()).unwrap();
+        p.options.push(DhcpOption::Unrecognized(RawDhcpOption {
+            code: 224,
+            data: (0..300).map(|i| i as u8).collect(),
+        }));
+
+        // The second piece only fits in the file field at the minimum size.
+        let mut buf = [0; 1500];
+        let encoded = p.encode(&mut buf);
+        assert!(encoded.len() <= 548);
+        assert_eq!(&encoded[108..110], &[224, 45]);
+        assert_eq!(Packet::from(encoded).unwrap(), p);
+
+        let encoded = p.encode_with_limit(&mut buf, 1500).unwrap();
+        assert_eq!(encoded[240], DHCP_MESSAGE_TYPE);
+        assert_eq!(Packet::from(encoded).unwrap(), p);
+    }
+
+    #[test]
+    fn max_message_size_limits_encoding() {
+        let mut p = Packet::from(&discover()).unwrap();
+        assert_eq!(p.max_message_size(), MIN_MAX_MESSAGE_SIZE);
+        p.options.push(DhcpOption::MaximumDhcpMessageSize(300));
+        assert_eq!(p.max_message_size(), MIN_MAX_MESSAGE_SIZE);
+        p.options.pop();
+        p.options.push(DhcpOption::MaximumDhcpMessageSize(1500));
+        assert_eq!(p.max_message_size(), 1500);
+
+        for code in 224..229 {
+            p.options.push(DhcpOption::Unrecognized(RawDhcpOption {
+                code,
+                data: vec![0; 200],
+            }));
+        }
+        let mut buf = [0; 1500];
+        let e = p.encode_with_limit(&mut buf, 576).unwrap_err();
+        assert_eq!(e.codes, vec![225, 226, 227, 228]);
+        assert!(e.len <= 548);
+        let encoded = p.encode_with_limit(&mut buf, p.max_message_size()).unwrap();
+        assert!(encoded.len() <= 1500 - 28);
+        assert_eq!(Packet::from(encoded).unwrap(), p);
+    }
+
+    #[test]
+    fn errors_locate_the_problem() {
+        fn error(captured: &[u8]) -> (usize, Option<u8>, DecodeErrorKind) {
+            let e = Packet::decode(captured, DecodeMode::Strict).unwrap_err();
+            (e.offset, e.option, e.kind)
+        }
+
+        assert_eq!(
+            error(&discover()[..100]),
+            (100, None, DecodeErrorKind::Truncated)
+        );
+
+        let mut captured = discover();
+        captured[0] = 3;
+        assert_eq!(error(&captured), (0, None, DecodeErrorKind::InvalidOp(3)));
+
+        let mut captured = discover();
+        captured[2] = 17;
+        assert_eq!(
+            error(&captured),
+            (2, None, DecodeErrorKind::InvalidHlen(17))
+        );
+
+        let mut captured = discover();
+        captured[236] = 0;
+        assert_eq!(error(&captured), (236, None, DecodeErrorKind::BadCookie));
+        assert!(Packet::from(&captured).unwrap().is_bootp());
+
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[53, 1, 1, 12, 10, b'a']);
+        assert_eq!(
+            error(&captured[..246]),
+            (243, Some(HOST_NAME), DecodeErrorKind::TruncatedOption)
+        );
+
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[53, 1, 0, 255]);
+        assert_eq!(
+            error(&captured),
+            (
+                240,
+                Some(DHCP_MESSAGE_TYPE),
+                DecodeErrorKind::UnrecognizedMessageType(0)
+            )
+        );
+
+        let captured = capture(
+            &CLIENT_HEADER,
+            &[],
+            &[],
+            &[53, 1, 1, 12, 2, 0xff, 0xfe, 255],
+        );
+        let e = Packet::decode(&captured, DecodeMode::Strict).unwrap_err();
+        assert_eq!(
+            (e.offset, e.option, e.kind.clone()),
+            (243, Some(HOST_NAME), DecodeErrorKind::NonUtf8String)
+        );
+        assert_eq!(
+            e.to_string(),
+            "string is not valid UTF-8 in option 12 at offset 243"
+        );
+
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[53, 1, 1, 54, 3, 1, 2, 3, 255]);
+        assert_eq!(
+            error(&captured),
+            (
+                243,
+                Some(SERVER_IDENTIFIER),
+                DecodeErrorKind::InvalidLength(3)
+            )
+        );
+    }
+
+    #[test]
+    fn typed_options_round_trip() {
+        let mut p = Packet::from(&ack()).unwrap();
+        let addr = Ipv4Addr::new(192, 168, 0, 1);
+        p.options.extend(vec![
+            DhcpOption::TimeOffset(-3600),
+            DhcpOption::NetworkTimeProtocolServers(vec![addr, Ipv4Addr::new(192, 168, 0, 2)]),
+            DhcpOption::DomainName("example.com".to_string()),
+            DhcpOption::IpForwarding(false),
+            DhcpOption::PolicyFilter(vec![(addr, Ipv4Addr::new(255, 255, 255, 0))]),
+            DhcpOption::PathMtuPlateauTable(vec![68, 1500]),
+            DhcpOption::InterfaceMtu(1500),
+            DhcpOption::BroadcastAddress(Ipv4Addr::new(192, 168, 0, 255)),
+            DhcpOption::StaticRoute(vec![(Ipv4Addr::new(10, 0, 0, 0), addr)]),
+            DhcpOption::TcpKeepaliveGarbage(true),
+            DhcpOption::NetbiosNodeType(8),
+            DhcpOption::MobileIpHomeAgent(vec![]),
+            DhcpOption::VendorClassIdentifier(b"PXEClient".to_vec()),
+            DhcpOption::TftpServerName("tftp.example.com".to_string()),
+            DhcpOption::ClientArchitecture(vec![7]),
+        ]);
+        for o in &p.options {
+            assert_eq!(o.validate(), Ok(()), "{:?}", o);
+        }
+
+        let mut buf = [0; 1500];
+        let encoded = p.encode_with_limit(&mut buf, 1500).unwrap();
+        assert_eq!(Packet::decode(encoded, DecodeMode::Strict).unwrap(), p);
+    }
+
+    #[test]
+    fn typed_options_are_validated() {
+        let invalid = |code: u8, data: &[u8]| decode_option_data(code, data).unwrap_err();
+        assert_eq!(
+            invalid(INTERFACE_MTU, &[0, 67]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(IP_FORWARDING_ENABLE_DISABLE, &[2]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(NETBIOS_OVER_TCPIP_NODE_TYPE, &[3]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(STATIC_ROUTE, &[0; 8]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(POLICY_FILTER, &[1; 4]),
+            DecodeErrorKind::InvalidLength(4)
+        );
+        assert_eq!(invalid(TIME_SERVER, &[]), DecodeErrorKind::InvalidLength(0));
+        assert_eq!(invalid(DOMAIN_NAME, &[]), DecodeErrorKind::InvalidLength(0));
+        assert_eq!(
+            invalid(CLIENT_IDENTIFIER, &[1]),
+            DecodeErrorKind::InvalidLength(1)
+        );
+
+        assert_eq!(
+            DhcpOption::DefaultIpTimeToLive(0).validate(),
+            Err(DecodeErrorKind::InvalidValue)
+        );
+        assert_eq!(
+            DhcpOption::Router(vec![]).validate(),
+            Err(DecodeErrorKind::InvalidLength(0))
+        );
+    }
+
+    #[test]
+    fn classless_static_routes() {
+        let router = Ipv4Addr::new(192, 168, 0, 1);
+        let net = |a, b, c, d, len| Ipv4Net::new(Ipv4Addr::new(a, b, c, d), len).unwrap();
+        let routes = DhcpOption::ClasslessStaticRoute(vec![
+            (net(0, 0, 0, 0, 0), router),
+            (net(10, 0, 0, 0, 8), router),
+            (net(10, 229, 0, 128, 25), router),
+        ]);
+        let data = vec![
+            0, 192, 168, 0, 1, 8, 10, 192, 168, 0, 1, 25, 10, 229, 0, 128, 192, 168, 0, 1,
+        ];
+        assert_eq!(routes.to_raw().data, data);
+        assert_eq!(
+            decode_option_data(CLASSLESS_ROUTE_FORMAT, &data),
+            Ok(routes)
+        );
+
+        let invalid = |data: &[u8]| decode_option_data(CLASSLESS_ROUTE_FORMAT, data).unwrap_err();
+        assert_eq!(
+            invalid(&[9, 10, 64, 1, 2, 3, 4]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(&[33, 10, 0, 0, 0, 1, 2, 3, 4]),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(&[24, 10, 0, 0, 1, 2]),
+            DecodeErrorKind::InvalidLength(6)
+        );
+        assert_eq!(invalid(&[]), DecodeErrorKind::InvalidLength(0));
+
+        assert_eq!(Ipv4Net::new(Ipv4Addr::new(10, 0, 0, 1), 24), None);
+        assert_eq!(net(10, 229, 0, 128, 25).to_string(), "10.229.0.128/25");
+        assert!(net(10, 0, 0, 0, 8).contains(Ipv4Addr::new(10, 1, 2, 3)));
+    }
+
+    #[test]
+    fn relay_agent_information() {
+        let mut options = vec![53, 1, 1];
+        options.extend(&[82, 26, 1, 4, b'e', b't', b'h', b'0', 2, 2, 0xbe, 0xef]);
+        options.extend(&[5, 4, 10, 1, 0, 0, 11, 4, 10, 0, 0, 254, 6, 2, b'4', b'2']);
+        options.extend(&[255]);
+        let mut header = CLIENT_HEADER;
+        header[24..28].copy_from_slice(&[10, 0, 0, 254]);
+        let captured = capture(&header, &[], &[], &options);
+
+        let p = Packet::decode(&captured, DecodeMode::Strict).unwrap();
+        assert_eq!(p.circuit_id(), Some(&b"eth0"[..]));
+        assert_eq!(p.remote_id(), Some(&[0xbe, 0xef][..]));
+        assert_eq!(p.link_selection(), Some(Ipv4Addr::new(10, 1, 0, 0)));
+        assert_eq!(
+            p.server_identifier_override(),
+            Some(Ipv4Addr::new(10, 0, 0, 254))
+        );
+        assert_eq!(p.subscriber_id(), Some("42"));
+        assert_eq!(p.relay_agent_information().unwrap().len(), 5);
+        assert_round_trip(&captured);
+
+        // A sub-option too long for its length octet is left out, not sent corrupt.
+        let long =
+            DhcpOption::RelayAgentInformation(vec![RelayAgentSubOption::CircuitId(vec![0; 256])]);
+        assert_eq!(long.validate(), Err(DecodeErrorKind::InvalidLength(256)));
+        let mut p = Packet::from(&captured).unwrap();
+        p.set_option(long);
+        let mut buf = [0; 1500];
+        let e = p.encode_with_limit(&mut buf, 1500).unwrap_err();
+        assert_eq!(
+            e.invalid,
+            vec![(RELAY_AGENT_INFORMATION, DecodeErrorKind::InvalidLength(256))]
+        );
+        let sent = Packet::from(&buf[..e.len]).unwrap();
+        assert_eq!(sent.relay_agent_information(), None);
+        assert_eq!(
+            decode_option_data(RELAY_AGENT_INFORMATION, &[1, 4, 1, 2]),
+            Err(DecodeErrorKind::InvalidLength(4))
+        );
+    }
+
+    #[test]
+    fn client_identifier_forms() {
+        let decode = |data: &[u8]| match decode_option_data(CLIENT_IDENTIFIER, data) {
+            Ok(DhcpOption::ClientIdentifier(id)) => {
+                assert_eq!(id.to_bytes(), data);
+                id
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        let mac = [0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42];
+
+        let hw = decode(&[&[1][..], &mac].concat());
+        assert_eq!(
+            hw,
+            ClientIdentifier::HardwareAddr {
+                htype: 1,
+                addr: mac.to_vec()
+            }
+        );
+        let duid_llt = [255, 0, 0, 0, 7, 0, 1, 0, 1, 0x2a, 0x5b, 0x6c, 0x7d];
+        assert_eq!(
+            decode(&[&duid_llt[..], &mac].concat()),
+            ClientIdentifier::Duid {
+                iaid: 7,
+                duid: Duid::LinkLayerTime {
+                    hardware_type: 1,
+                    time: 0x2a5b6c7d,
+                    addr: mac.to_vec(),
+                }
+            }
+        );
+        assert_eq!(
+            decode(&[255, 0, 0, 0, 1, 0, 2, 0, 0, 0x01, 0x37, 0xab]),
+            ClientIdentifier::Duid {
+                iaid: 1,
+                duid: Duid::Enterprise {
+                    enterprise_number: 311,
+                    id: vec![0xab],
+                }
+            }
+        );
+        let uuid = decode(&[&[255, 0, 0, 0, 1, 0, 4][..], &[0x11; 16]].concat());
+        assert_eq!(
+            uuid,
+            ClientIdentifier::Duid {
+                iaid: 1,
+                duid: Duid::Uuid([0x11; 16])
+            }
+        );
+        assert_eq!(
+            decode(b"\0router-7"),
+            ClientIdentifier::Opaque(b"router-7".to_vec())
+        );
+
+        let invalid = |data: &[u8]| decode_option_data(CLIENT_IDENTIFIER, data).unwrap_err();
+        assert_eq!(invalid(&[1]), DecodeErrorKind::InvalidLength(1));
+        assert_eq!(invalid(&[255, 0, 0, 0]), DecodeErrorKind::InvalidLength(4));
+        assert_eq!(
+            invalid(&[255, 0, 0, 0, 1, 0, 4, 1]),
+            DecodeErrorKind::InvalidLength(3)
+        );
+
+        let mut p = Packet::from(&discover()).unwrap();
+        assert_eq!(p.client_key(), ClientKey::ClientIdentifier(hw));
+        p.options.retain(|o| o.code() != CLIENT_IDENTIFIER);
+        assert_eq!(p.client_key(), ClientKey::HardwareAddr(p.chaddr));
+    }
+
+    #[test]
+    fn client_fqdn() {
+        let decode = |data: &[u8]| match decode_option_data(CLIENT_FQDN, data) {
+            Ok(DhcpOption::ClientFqdn(fqdn)) => {
+                assert_eq!(fqdn.to_bytes(), data);
+                fqdn
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+
+        let wire = decode(b"\x05\0\0\x04host\x07example\x03com\0");
+        assert_eq!(wire.flags, FQDN_FLAG_E | FQDN_FLAG_S);
+        assert_eq!(wire.name(), "host.example.com.");
+        let mut built = ClientFqdn::new("host.example.com.").unwrap();
+        built.flags |= FQDN_FLAG_S;
+        assert_eq!(built, wire);
+
+        let partial = decode(b"\x04\0\0\x04host");
+        assert!(!partial.fully_qualified);
+        assert_eq!(partial.name(), "host");
+
+        let ascii = decode(b"\0\0\0host.example.com");
+        assert_eq!(ascii.labels, vec!["host", "example", "com"]);
+        assert!(!ascii.fully_qualified);
+        assert_eq!(decode(&[FQDN_FLAG_N, 0, 0]).labels, Vec::<String>::new());
+
+        let reply = wire.reply(false);
+        assert_eq!(reply.flags, FQDN_FLAG_E | FQDN_FLAG_O);
+        assert_eq!((reply.rcode1, reply.rcode2), (255, 255));
+        assert_eq!(
+            partial.reply(true).flags,
+            FQDN_FLAG_E | FQDN_FLAG_S | FQDN_FLAG_O
+        );
+        assert_eq!(wire.reply(true).flags, FQDN_FLAG_E | FQDN_FLAG_S);
+
+        let invalid = |data: &[u8]| decode_option_data(CLIENT_FQDN, data).unwrap_err();
+        assert_eq!(invalid(&[0x09, 0, 0]), DecodeErrorKind::InvalidValue);
+        assert_eq!(invalid(&[0x10, 0, 0]), DecodeErrorKind::InvalidValue);
+        assert_eq!(
+            invalid(b"\x04\0\0\x05host"),
+            DecodeErrorKind::InvalidLength(5)
+        );
+        assert_eq!(invalid(b"\x04\0\0\x04ho_t"), DecodeErrorKind::InvalidValue);
+        assert_eq!(
+            invalid(b"\x04\0\0\0\x01a"),
+            DecodeErrorKind::InvalidLength(3)
+        );
+        assert_eq!(invalid(b"\0\0\0host..com"), DecodeErrorKind::InvalidValue);
+        assert_eq!(ClientFqdn::new("-host.example.com"), None);
+    }
+
+    #[test]
+    fn domain_search_compression() {
+        // The example from RFC 3397, section 2.
+        let search = DhcpOption::DomainSearch(vec![
+            "eng.apple.com".to_string(),
+            "marketing.apple.com".to_string(),
+        ]);
+        let data = b"\x03eng\x05apple\x03com\0\x09marketing\xc0\x04";
+        assert_eq!(search.to_raw().data, &data[..]);
+        assert_eq!(decode_option_data(DOMAIN_SEARCH, data), Ok(search));
+
+        let invalid = |data: &[u8]| decode_option_data(DOMAIN_SEARCH, data).unwrap_err();
+        assert_eq!(invalid(&[0xc0, 0]), DecodeErrorKind::InvalidValue);
+        assert_eq!(invalid(b"\x01a\xc0\x00"), DecodeErrorKind::InvalidValue);
+        assert_eq!(
+            invalid(b"\x01a\0\x01b\xc0\x03"),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(
+            invalid(b"\x01a\0\xc0\x06\x01b\0"),
+            DecodeErrorKind::InvalidValue
+        );
+        assert_eq!(invalid(b"\x03com"), DecodeErrorKind::InvalidLength(4));
+        assert_eq!(invalid(&[0x40]), DecodeErrorKind::InvalidValue);
+
+        let search = |name: String| DhcpOption::DomainSearch(vec![name]).validate();
+        assert_eq!(search("a.b".to_string()), Ok(()));
+        assert_eq!(
+            search("a..b".to_string()),
+            Err(DecodeErrorKind::InvalidValue)
+        );
+        assert_eq!(search("a.".to_string()), Err(DecodeErrorKind::InvalidValue));
+        assert_eq!(search(String::new()), Err(DecodeErrorKind::InvalidValue));
+        assert_eq!(search("a".repeat(63)), Ok(()));
+        assert_eq!(search("a".repeat(64)), Err(DecodeErrorKind::InvalidValue));
+        // 253 characters take 255 octets in wire format.
+        let name = |len: usize| vec!["a".repeat(63); 4].join(".")[..len].to_string();
+        assert_eq!(search(name(253)), Ok(()));
+        assert_eq!(
+            search(name(251) + ".ab"),
+            Err(DecodeErrorKind::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn long_domain_search_is_split() {
+        let names: Vec<String> = (0..30)
+            .map(|i| format!("site{}.branch{}.corp.example.com", i, i))
+            .collect();
+        let mut p = Packet::from(&ack()).unwrap();
+        p.options.push(DhcpOption::DomainSearch(names));
+        assert!(p.options.last().unwrap().to_raw().data.len() > 255);
+
+        let mut buf = [0; 1500];
+        let encoded = p.encode_with_limit(&mut buf, 1500).unwrap();
+        assert_eq!(Packet::decode(encoded, DecodeMode::Strict).unwrap(), p);
+    }
+
+    #[test]
+    fn user_and_vendor_identifying_classes() {
+        let decode = |code: u8, data: &[u8]| decode_option_data(code, data).unwrap();
+
+        let classes = decode(USER_CLASS, b"\x04iPXE\x03lab");
+        assert_eq!(
+            classes,
+            DhcpOption::UserClass(vec![b"iPXE".to_vec(), b"lab".to_vec()])
+        );
+        assert_eq!(classes.to_raw().data, b"\x04iPXE\x03lab");
+        assert_eq!(
+            decode(USER_CLASS, b"iPXE"),
+            DhcpOption::UserClass(vec![b"iPXE".to_vec()])
+        );
+
+        // Broadband Forum enterprise number 3561, as CPE send it.
+        let class = b"\0\0\x0d\xe9\x05\x04dslf";
+        let vendor_class = decode(V_I_VENDOR_CLASS, class);
+        assert_eq!(
+            vendor_class,
+            DhcpOption::ViVendorClass(vec![(3561, vec![b"dslf".to_vec()])])
+        );
+        assert_eq!(vendor_class.to_raw().data, &class[..]);
+
+        let info = b"\0\0\x0d\xe9\x08\x01\x02ab\x02\x02cd\0\0\0\x09\0";
+        let vendor_info = decode(V_I_VENDOR_SPECIFIC_INFORMATION, info);
+        assert_eq!(
+            vendor_info,
+            DhcpOption::ViVendorSpecificInformation(vec![
+                (
+                    3561,
+                    vec![
+                        RawDhcpOption {
+                            code: 1,
+                            data: b"ab".to_vec()
+                        },
+                        RawDhcpOption {
+                            code: 2,
+                            data: b"cd".to_vec()
+                        },
+                    ]
+                ),
+                (9, vec![]),
+            ])
+        );
+        assert_eq!(vendor_info.to_raw().data, &info[..]);
+
+        let invalid = |code: u8, data: &[u8]| decode_option_data(code, data).unwrap_err();
+        assert_eq!(
+            invalid(V_I_VENDOR_CLASS, b"\0\0\x0d\xe9\x06\x04dslf"),
+            DecodeErrorKind::InvalidLength(10)
+        );
+        assert_eq!(
+            invalid(V_I_VENDOR_SPECIFIC_INFORMATION, b"\0\0\0\x09\x02\x01\x05"),
+            DecodeErrorKind::InvalidLength(7)
+        );
+    }
+
+    #[test]
+    fn long_classes_and_vendor_blocks() {
+        let sub = |code: u8, len: usize| RawDhcpOption {
+            code,
+            data: vec![code; len],
+        };
+        // Values over 255 octets are split over several options and joined again.
+        let mut p = Packet::from(&ack()).unwrap();
+        p.options.push(DhcpOption::UserClass(vec![
+            vec![b'a'; 255],
+            vec![b'b'; 200],
+        ]));
+        p.options.push(DhcpOption::ViVendorClass(vec![
+            (3561, vec![vec![b'c'; 250]]),
+            (9, vec![vec![b'd'; 20], vec![b'e'; 20]]),
+        ]));
+        p.options.push(DhcpOption::ViVendorSpecificInformation(vec![
+            (3561, vec![sub(1, 200), sub(2, 51)]),
+            (9, vec![sub(3, 20)]),
+        ]));
+        let mut buf = [0; 1500];
+        let encoded = p.encode_with_limit(&mut buf, 1500).unwrap();
+        assert_eq!(Packet::decode(encoded, DecodeMode::Strict).unwrap(), p);
+
+        // A length octet that would wrap leaves the option out instead.
+        let too_long = [
+            DhcpOption::UserClass(vec![vec![b'a'; 300]]),
+            DhcpOption::ViVendorClass(vec![(9, vec![vec![b'c'; 256]])]),
+            DhcpOption::ViVendorClass(vec![(9, vec![vec![b'c'; 150], vec![b'd'; 150]])]),
+            DhcpOption::ViVendorSpecificInformation(vec![(9, vec![sub(1, 256)])]),
+            DhcpOption::ViVendorSpecificInformation(vec![(9, vec![sub(1, 200), sub(2, 60)])]),
+        ];
+        let lens: Vec<usize> = too_long
+            .iter()
+            .map(|option| match option.validate() {
+                Err(DecodeErrorKind::InvalidLength(len)) => len,
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(lens, vec![300, 256, 302, 256, 264]);
+
+        let mut p = Packet::from(&ack()).unwrap();
+        p.options.push(too_long[0].clone());
+        let e = p.encode_with_limit(&mut buf, 1500).unwrap_err();
+        assert_eq!(
+            e.invalid,
+            vec![(USER_CLASS, DecodeErrorKind::InvalidLength(300))]
+        );
+        assert_eq!(
+            Packet::from(&buf[..e.len]).unwrap().option(USER_CLASS),
+            None
+        );
+    }
+
+    #[test]
+    fn modern_options() {
+        let decode = |code: u8, data: &[u8]| decode_option_data(code, data);
+        assert_eq!(decode(RAPID_COMMIT, &[]), Ok(DhcpOption::RapidCommit));
+        assert_eq!(
+            decode(RAPID_COMMIT, &[1]),
+            Err(DecodeErrorKind::InvalidLength(1))
+        );
+        assert_eq!(
+            decode(IPV6_ONLY_PREFERRED, &[0, 0, 0x07, 0x08]),
+            Ok(DhcpOption::Ipv6OnlyPreferred(1800))
+        );
+        assert_eq!(
+            decode(CAPTIVE_PORTAL, b"https://portal.example/api"),
+            Ok(DhcpOption::CaptivePortal(
+                "https://portal.example/api".to_string()
+            ))
+        );
+        assert_eq!(
+            decode(AUTO_CONFIGURE, &[0]),
+            Ok(DhcpOption::AutoConfigure(false))
+        );
+        assert!(decode(AUTO_CONFIGURE, &[0, 1]).is_err());
+
+        let p = crate::builder::PacketBuilder::new(HardwareAddr::ethernet([0; 6]))
+            .message_type(MessageType::Discover)
+            .option(DhcpOption::RapidCommit)
+            .option(DhcpOption::ParameterRequestList(vec![IPV6_ONLY_PREFERRED]))
+            .build();
+        let mut buf = [0; 1500];
+        let decoded = Packet::from(p.encode(&mut buf)).unwrap();
+        assert_eq!(decoded.option(RAPID_COMMIT), Some(&DhcpOption::RapidCommit));
+        assert!(decoded.requests_option(IPV6_ONLY_PREFERRED));
+        assert!(!decoded.requests_option(CAPTIVE_PORTAL));
+        assert!(!decoded.forcerenew_nonce_capable());
+
+        assert!(decode(AUTHENTICATION, &[3, 1, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        let mut p = decoded;
+        p.options.push(DhcpOption::ForcerenewNonceCapable(vec![
+            AUTH_ALGORITHM_HMAC_MD5,
+        ]));
+        let decoded = Packet::from(p.encode(&mut buf)).unwrap();
+        assert!(decoded.forcerenew_nonce_capable());
+    }
+
+    #[test]
+    fn message_types() {
+        for val in 1..=255 {
+            let mtype = MessageType::try_from(val).unwrap();
+            assert_eq!(mtype.code(), val);
+            assert_eq!(matches!(mtype, MessageType::Other(_)), val > 18);
+            let option = DhcpOption::DhcpMessageType(mtype);
+            assert_eq!(decode_option_data(DHCP_MESSAGE_TYPE, &[val]), Ok(option));
+        }
+        assert_eq!(
+            MessageType::try_from(0),
+            Err(DecodeErrorKind::UnrecognizedMessageType(0))
+        );
+        assert_eq!(MessageType::try_from(9), Ok(MessageType::ForceRenew));
+        assert_eq!(MessageType::try_from(19), Ok(MessageType::Other(19)));
+        assert_ne!(MessageType::Other(5), MessageType::Ack);
+        let option = |mtype| DhcpOption::DhcpMessageType(mtype).validate();
+        assert_eq!(option(MessageType::Other(19)), Ok(()));
+        assert_eq!(
+            option(MessageType::Other(5)),
+            Err(DecodeErrorKind::InvalidValue)
+        );
+        assert_eq!(
+            MessageType::LeaseQueryDone.to_string(),
+            "DHCPLEASEQUERYDONE"
+        );
+        assert_eq!(MessageType::Other(42).to_string(), "DHCP message type 42");
+    }
+
+    #[test]
+    fn typed_accessors_and_setters() {
+        let addr = Ipv4Addr::new(192, 168, 0, 10);
+        let mut p = crate::builder::PacketBuilder::new(HardwareAddr::ethernet([0; 6]))
+            .option(DhcpOption::HostName("a".to_string()))
+            .option(DhcpOption::Router(vec![addr]))
+            .option(DhcpOption::HostName("b".to_string()))
+            .build();
+        assert_eq!(p.options_with_code(HOST_NAME).count(), 2);
+        assert_eq!(p.hostname(), Some("a"));
+
+        p.set_hostname("web");
+        p.set_lease_time(3600);
+        p.set_option(DhcpOption::DhcpMessageType(MessageType::Request));
+        assert_eq!(
+            p.options,
+            vec![
+                DhcpOption::DhcpMessageType(MessageType::Request),
+                DhcpOption::HostName("web".to_string()),
+                DhcpOption::Router(vec![addr]),
+                DhcpOption::IpAddressLeaseTime(3600),
+            ]
+        );
+
+        p.set_requested_ip(addr);
+        p.set_server_identifier(addr);
+        p.set_parameter_request_list(vec![SUBNET_MASK, ROUTER]);
+        p.set_vendor_class(b"PXEClient");
+        p.set_client_id(ClientIdentifier::Opaque(b"id".to_vec()));
+        assert_eq!(p.requested_ip(), Some(addr));
+        assert_eq!(p.server_identifier(), Some(addr));
+        assert_eq!(p.lease_time(), Some(3600));
+        assert_eq!(p.parameter_request_list(), Some(&[SUBNET_MASK, ROUTER][..]));
+        assert!(p.requests_option(ROUTER));
+        assert_eq!(p.vendor_class(), Some(&b"PXEClient"[..]));
+        assert_eq!(
+            p.client_id(),
+            Some(&ClientIdentifier::Opaque(b"id".to_vec()))
+        );
+
+        assert_eq!(
+            p.remove_option(HOST_NAME),
+            Some(DhcpOption::HostName("web".to_string()))
+        );
+        assert_eq!(p.hostname(), None);
+        assert_eq!(p.remove_option(HOST_NAME), None);
+    }
+
+    #[test]
+    fn pad_and_end_are_single_bytes() {
+        let captured = capture(
+            &CLIENT_HEADER,
+            &[],
+            &[],
+            &[
+                0, 0, 53, 1, 1, 0, 50, 4, 10, 0, 0, 9, 0, 0, 255, 12, 1, b'x', 255,
+            ],
+        );
+        let p = Packet::decode(&captured, DecodeMode::Strict).unwrap();
+        assert_eq!(
+            p.options,
+            vec![
+                DhcpOption::DhcpMessageType(MessageType::Discover),
+                DhcpOption::RequestedIpAddress(Ipv4Addr::new(10, 0, 0, 9)),
+            ]
+        );
+
+        // Options starting with END, or missing END altogether.
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[255, 53, 1, 1]);
+        assert!(Packet::from(&captured).unwrap().options.is_empty());
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[53, 1, 1]);
+        assert_eq!(Packet::from(&captured[..243]).unwrap().options.len(), 1);
+
+        assert_eq!(
+            decode_option(&[0, 0, 255]).unwrap_err().kind,
+            DecodeErrorKind::EndOfOptions
+        );
+        assert_eq!(decode_option(&[0, 53, 1, 5]).unwrap().1.code(), 53);
+    }
+
+    #[test]
+    fn lenient_mode_keeps_malformed_options() {
+        let captured = capture(
+            &CLIENT_HEADER,
+            &[],
+            &[],
+            &[
+                53, 1, 0, 12, 2, 0xff, 0xfe, 54, 3, 1, 2, 3, 51, 4, 0, 0, 0, 60, 1, 9,
+            ],
+        );
+        let p = Packet::from(&captured[..260]).unwrap();
+        let raw = |code: u8, data: &[u8]| {
+            DhcpOption::Unrecognized(RawDhcpOption {
+                code,
+                data: data.to_vec(),
+            })
+        };
+        assert_eq!(
+            p.options,
+            vec![
+                raw(DHCP_MESSAGE_TYPE, &[0]),
+                raw(HOST_NAME, &[0xff, 0xfe]),
+                raw(SERVER_IDENTIFIER, &[1, 2, 3]),
+                DhcpOption::IpAddressLeaseTime(60),
+            ]
+        );
+        assert!(Packet::decode(&captured[..260], DecodeMode::Strict).is_err());
+
+        let mut buf = [0; 1500];
+        assert_eq!(Packet::from(p.encode(&mut buf)).unwrap(), p);
+    }
+
+    #[test]
+    fn view_matches_packet() {
+        for captured in &[discover(), request(), ack()] {
+            let view = PacketView::new(captured).unwrap();
+            let p = view.to_packet().unwrap();
+            assert_eq!(view.reply(), p.reply);
+            assert_eq!(view.xid(), p.xid);
+            assert_eq!(view.flags(), p.flags);
+            assert_eq!(view.yiaddr(), p.yiaddr);
+            assert_eq!(view.siaddr(), p.siaddr);
+            assert_eq!(view.chaddr(), p.chaddr);
+            assert_eq!(view.message_type(), p.message_type().ok());
+            for option in &p.options {
+                assert_eq!(view.option(option.code()).as_ref(), Some(option));
+            }
+            assert_eq!(view.raw_options().count(), p.options.len());
+        }
+
+        let mut captured = discover();
+        captured[0] = 0;
+        assert_eq!(
+            PacketView::new(&captured).unwrap_err().kind,
+            DecodeErrorKind::InvalidOp(0)
+        );
+    }
+
+    #[test]
+    fn view_follows_overload_and_joins_instances() {
+        let mut sname = vec![3, 4, 10, 0, 0, 3, 255];
+        sname.resize(64, 0);
+        let captured = capture(
+            &SERVER_HEADER,
+            &sname,
+            &[3, 4, 10, 0, 0, 2, 255],
+            &[53, 1, 5, 3, 4, 10, 0, 0, 1, 52, 1, 3, 255],
+        );
+        let view = PacketView::new(&captured).unwrap();
+        assert_eq!(
+            view.raw_options().map(|(code, _)| code).collect::<Vec<_>>(),
+            vec![DHCP_MESSAGE_TYPE, ROUTER, ROUTER, ROUTER]
+        );
+        assert!(matches!(
+            view.option_data(DHCP_MESSAGE_TYPE),
+            Some(Cow::Borrowed(_))
+        ));
+        assert_eq!(
+            view.option(ROUTER),
+            Some(DhcpOption::Router(vec![
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2),
+                Ipv4Addr::new(10, 0, 0, 3),
+            ]))
+        );
+        assert_eq!(
+            view.option(ROUTER).as_ref(),
+            view.to_packet().unwrap().option(ROUTER)
+        );
+        assert_eq!(view.option(HOST_NAME), None);
+    }
+
+    #[test]
+    fn bootp_without_cookie() {
+        let mut captured = CLIENT_HEADER.to_vec();
+        captured.resize(236, 0);
+        captured.extend_from_slice(b"vendor specific area");
+        captured.resize(300, 0);
+
+        let p = Packet::from(&captured).unwrap();
+        assert!(p.is_bootp());
+        assert!(p.options.is_empty());
+        assert_eq!(p.vend.as_deref(), Some(&captured[236..]));
+        let mut buf = [0; 1500];
+        assert_eq!(p.encode(&mut buf), &captured[..]);
+
+        let view = PacketView::new(&captured).unwrap();
+        assert_eq!(view.vend(), Some(&captured[236..]));
+        assert_eq!(view.raw_options().count(), 0);
+
+        // Strict mode only takes an empty vendor area.
+        let e = Packet::decode(&captured, DecodeMode::Strict).unwrap_err();
+        assert_eq!((e.offset, e.kind), (236, DecodeErrorKind::BadCookie));
+        captured[236..].iter_mut().for_each(|b| *b = 0);
+        let p = Packet::decode(&captured, DecodeMode::Strict).unwrap();
+        assert_eq!(p.vend.as_deref(), Some(&captured[236..]));
+        let p = Packet::decode(&captured[..236], DecodeMode::Strict).unwrap();
+        assert!(p.is_bootp());
+    }
+
+    #[test]
+    fn bootp_with_vendor_extensions() {
+        let captured = capture(&CLIENT_HEADER, &[], &[], &[1, 4, 255, 255, 255, 0, 255]);
+        let mut p = Packet::from(&captured).unwrap();
+        assert!(p.is_bootp());
+        assert_eq!(p.vend, None);
+
+        // Replies are 300 octets and never overload sname or file.
+        p.reply = true;
+        p.options.push(DhcpOption::HostName("h".repeat(60)));
+        let mut buf = [0; 1500];
+        let e = p.encode_with_limit(&mut buf, 300 + 28).unwrap_err();
+        assert_eq!(e.codes, vec![HOST_NAME]);
+        assert_eq!(e.len, 300);
+        assert_eq!(&buf[240..247], &[1, 4, 255, 255, 255, 0, 255]);
+        assert_eq!(buf[44], 0);
+        assert_eq!(buf[108], 0);
+    }
+}
@@ -1,22 +1,118 @@
 //! This is a convenience module that simplifies the writing of a DHCP server service.
 
-use std::cell::Cell;
+use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
+use crate::auth;
+use crate::builder::{self, PacketBuilder};
+use crate::custom::OptionRegistry;
 use crate::options;
 use crate::options::{DhcpOption, MessageType};
 use crate::packet::*;
+use crate::vendor::{EncodeError, VendorRegistry};
 
 pub struct Server {
-    out_buf: Cell<[u8; 1500]>,
     socket: UdpSocket,
     src: SocketAddr,
     server_ip: Ipv4Addr,
     broadcast_ip: Ipv4Addr,
+    config: ServerConfig,
+    /// Last replay detection value sent in an Authentication option.
+    replay_detection: AtomicU64,
 }
 
+/// Settings for the replies `Server` builds.
+#[derive(Clone, Debug, Default)]
+pub struct ServerConfig {
+    /// Repeat classless static routes (option 121) as option 249, for Windows
+    /// clients that predate RFC 3442.
+    pub legacy_classless_static_route: bool,
+    /// How to encode Vendor Specific Information (option 43) for each vendor class.
+    pub vendors: VendorRegistry,
+    /// Custom options to decode requests with.
+    pub custom_options: OptionRegistry,
+    /// V6ONLY_WAIT in seconds, for clients that prefer to run IPv6-only (RFC 8925).
+    pub ipv6_only_wait: Option<u32>,
+    /// Captive portal API URI to announce (RFC 8910).
+    pub captive_portal: Option<String>,
+    /// Answer a DHCPDISCOVER carrying Rapid Commit with a DHCPACK (RFC 4039).
+    pub rapid_commit: bool,
+    /// Give clients that can use one a nonce to authenticate FORCERENEW with (RFC 6704).
+    pub forcerenew_nonce: bool,
+    /// Give clients that ask for Reconfigure Key authentication a key to authenticate
+    /// FORCERENEW with (RFC 3118, RFC 3315 section 21.5).
+    pub reconfigure_key: bool,
+}
+
+/// A BOOTP reply is 300 octets, which is all that older clients accept.
+const BOOTP_MAX_MESSAGE_SIZE: u16 = 300 + 28;
+
 pub trait Handler {
     fn handle_request(&mut self, server: &Server, in_packet: Packet);
+
+    /// Called with a datagram that could not be decoded, which is otherwise ignored.
+    fn handle_decode_error(&mut self, _server: &Server, _src: SocketAddr, _error: DecodeError) {}
+}
+
+/// Why a packet was not sent as given.
+#[derive(Debug)]
+pub enum SendError {
+    /// Nothing was sent.
+    Io(std::io::Error),
+    /// The packet was sent, `len` octets of it, without some of its options.
+    OptionsDropped {
+        len: usize,
+        /// Options that would not decode back as themselves (see `DhcpOption::validate`).
+        invalid: Vec<(u8, DecodeErrorKind)>,
+        /// Options that did not fit in the receiver's maximum message size.
+        too_large: Vec<u8>,
+    },
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::OptionsDropped {
+                invalid, too_large, ..
+            } => {
+                f.write_str("sent without")?;
+                for (code, kind) in invalid {
+                    write!(f, " invalid option {} ({})", code, kind)?;
+                }
+                if !too_large.is_empty() {
+                    write!(f, " options {:?} that did not fit", too_large)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::OptionsDropped { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SendError {
+    fn from(e: std::io::Error) -> SendError {
+        SendError::Io(e)
+    }
+}
+
+impl From<SendError> for std::io::Error {
+    fn from(e: SendError) -> std::io::Error {
+        match e {
+            SendError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
 }
 
 pub fn filter_options_by_req(opts: &mut Vec<DhcpOption>, req_params: &[u8]) {
@@ -70,29 +166,59 @@ pub fn filter_options_by_req(opts: &mut Vec<DhcpOption>, req_params: &[u8]) {
     opts.truncate(pos);
 }
 
+/// Puts the request's Relay Agent Information last in the reply, unchanged (RFC 3046).
+fn echo_relay_agent_information(opts: &mut Vec<DhcpOption>, req_packet: &Packet) {
+    opts.retain(|o| o.code() != options::RELAY_AGENT_INFORMATION);
+    if let Some(info) = req_packet.option(options::RELAY_AGENT_INFORMATION) {
+        opts.push(info.clone());
+    }
+}
+
 impl Server {
     pub fn serve<H: Handler>(
         udp_soc: UdpSocket,
         server_ip: Ipv4Addr,
         broadcast_ip: Ipv4Addr,
+        handler: H,
+    ) -> std::io::Error {
+        Server::serve_with(
+            udp_soc,
+            server_ip,
+            broadcast_ip,
+            ServerConfig::default(),
+            handler,
+        )
+    }
+
+    /// Like `serve`, with replies shaped by `config`.
+    pub fn serve_with<H: Handler>(
+        udp_soc: UdpSocket,
+        server_ip: Ipv4Addr,
+        broadcast_ip: Ipv4Addr,
+        config: ServerConfig,
         mut handler: H,
     ) -> std::io::Error {
         let mut in_buf: [u8; 1500] = [0; 1500];
         let mut s = Server {
-            out_buf: Cell::new([0; 1500]),
             socket: udp_soc,
             server_ip,
             broadcast_ip,
+            config,
+            replay_detection: AtomicU64::new(0),
             src: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
         };
         loop {
             match s.socket.recv_from(&mut in_buf) {
                 Err(e) => return e,
                 Ok((l, src)) => {
-                    if let Ok(p) = Packet::from(&in_buf[..l]) {
-                        s.src = src;
-
-                        handler.handle_request(&s, p);
+                    s.src = src;
+                    match Packet::decode_with(
+                        &in_buf[..l],
+                        DecodeMode::Lenient,
+                        &s.config.custom_options,
+                    ) {
+                        Ok(p) => handler.handle_request(&s, p),
+                        Err(e) => handler.handle_decode_error(&s, src, e),
                     }
                 }
             }
@@ -101,14 +227,31 @@ impl Server {
 
     /// Constructs and sends a reply packet back to the client.
     /// additional_options should not include DHCP_MESSAGE_TYPE nor SERVER_IDENTIFIER as these
-    /// are added automatically.
+    /// are added automatically. See `build_reply` for the rest of what goes in it.
     pub fn reply(
         &self,
         msg_type: MessageType,
         additional_options: Vec<DhcpOption>,
         offer_ip: Ipv4Addr,
         req_packet: Packet,
-    ) -> std::io::Result<usize> {
+    ) -> Result<usize, SendError> {
+        let max_message_size = req_packet.max_message_size();
+        self.send_with_limit(
+            self.build_reply(msg_type, additional_options, offer_ip, &req_packet),
+            max_message_size,
+        )
+    }
+
+    /// The reply `reply` sends. Relay agent information in the request is echoed back.
+    /// Options from the `ServerConfig` are added too, and an offer to a client that prefers
+    /// IPv6-only carries no address. Authentication options are sent whether requested or not.
+    pub fn build_reply(
+        &self,
+        msg_type: MessageType,
+        additional_options: Vec<DhcpOption>,
+        offer_ip: Ipv4Addr,
+        req_packet: &Packet,
+    ) -> Packet {
         let ciaddr = match msg_type {
             MessageType::Nak => Ipv4Addr::new(0, 0, 0, 0),
             _ => req_packet.ciaddr,
@@ -118,7 +261,11 @@ impl Server {
 
         let mut opts: Vec<DhcpOption> = Vec::with_capacity(additional_options.len() + 2);
         opts.push(DhcpOption::DhcpMessageType(msg_type));
-        opts.push(DhcpOption::ServerIdentifier(self.server_ip));
+        opts.push(DhcpOption::ServerIdentifier(
+            req_packet
+                .server_identifier_override()
+                .unwrap_or(self.server_ip),
+        ));
         /*opts.push(DhcpOption {
             code: options::DHCP_MESSAGE_TYPE,
             data: mt,
@@ -128,44 +275,658 @@ impl Server {
             data: &self.server_ip,
         });*/
         opts.extend(additional_options);
-
-        if let Some(DhcpOption::ParameterRequestList(prl)) =
-            req_packet.option(options::PARAMETER_REQUEST_LIST)
+        self.add_legacy_routes(&mut opts);
+        if let Some(uri) = &self.config.captive_portal {
+            opts.push(DhcpOption::CaptivePortal(uri.clone()));
+        }
+        // Clients preferring IPv6-only get no IPv4 address offered (RFC 8925).
+        let mut offer_ip = offer_ip;
+        if let (MessageType::Offer | MessageType::Ack, Some(wait)) =
+            (msg_type, self.ipv6_only_wait(req_packet))
         {
+            opts.push(DhcpOption::Ipv6OnlyPreferred(wait));
+            if msg_type == MessageType::Offer {
+                offer_ip = Ipv4Addr::UNSPECIFIED;
+            }
+        }
+
+        if let Some(prl) = req_packet.parameter_request_list() {
+            let auth: Vec<DhcpOption> = opts
+                .iter()
+                .filter(|o| o.code() == options::AUTHENTICATION)
+                .cloned()
+                .collect();
             filter_options_by_req(&mut opts, prl);
+            opts.retain(|o| o.code() != options::AUTHENTICATION);
+            opts.extend(auth);
+        }
+
+        // Rapid Commit is not in the request list, but an ACK to a DISCOVER must carry it.
+        if msg_type == MessageType::Ack && self.rapid_commit(req_packet) {
+            opts.push(DhcpOption::RapidCommit);
         }
+        echo_relay_agent_information(&mut opts, req_packet);
 
-        self.send(Packet {
-            reply: true,
-            hops: 0,
-            xid: req_packet.xid,
-            secs: 0,
-            broadcast: req_packet.broadcast,
-            ciaddr,
-            yiaddr: offer_ip,
-            siaddr: Ipv4Addr::new(0, 0, 0, 0),
-            giaddr: req_packet.giaddr,
-            chaddr: req_packet.chaddr,
-            options: opts,
-        })
+        PacketBuilder::reply_to(req_packet)
+            .ciaddr(ciaddr)
+            .yiaddr(offer_ip)
+            .options(opts)
+            .build()
+    }
+
+    /// Answers a BOOTP request (RFC 951) with a permanent address and the boot file to load
+    /// from `siaddr`. Vendor extensions (RFC 1497) are only sent if the client used them too.
+    pub fn reply_bootp(
+        &self,
+        yiaddr: Ipv4Addr,
+        siaddr: Ipv4Addr,
+        boot_file: &str,
+        additional_options: Vec<DhcpOption>,
+        req_packet: Packet,
+    ) -> Result<usize, SendError> {
+        if boot_file.len() >= 128 {
+            return Err(SendError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "boot file name longer than 127 bytes",
+            )));
+        }
+
+        let reply = PacketBuilder::reply_to(&req_packet)
+            .ciaddr(req_packet.ciaddr)
+            .yiaddr(yiaddr)
+            .siaddr(siaddr)
+            .file(boot_file);
+        let reply = match req_packet.vend {
+            Some(_) => reply.vend(vec![0; 64]),
+            None => {
+                let mut opts = additional_options;
+                echo_relay_agent_information(&mut opts, &req_packet);
+                reply.options(opts)
+            }
+        };
+
+        self.send_with_limit(reply.build(), BOOTP_MAX_MESSAGE_SIZE)
+    }
+
+    /// The V6ONLY_WAIT to give this client, if the server is configured with one and the
+    /// client asked for IPv6-Only Preferred. Such a client needs no IPv4 address (RFC 8925).
+    pub fn ipv6_only_wait(&self, req_packet: &Packet) -> Option<u32> {
+        match self.config.ipv6_only_wait {
+            Some(wait) if req_packet.requests_option(options::IPV6_ONLY_PREFERRED) => {
+                Some(wait.max(options::MIN_V6ONLY_WAIT))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether to commit a lease straight away and answer this DHCPDISCOVER with a
+    /// DHCPACK, skipping the offer (RFC 4039).
+    pub fn rapid_commit(&self, req_packet: &Packet) -> bool {
+        self.config.rapid_commit
+            && req_packet.message_type() == Ok(MessageType::Discover)
+            && req_packet.option(options::RAPID_COMMIT).is_some()
+    }
+
+    /// A new nonce for the lease of a client that can authenticate FORCERENEW with one, if
+    /// the server is configured to hand them out. The handler keeps the nonce with the lease,
+    /// to pass to `forcerenew` later, and adds the option to the DHCPACK. Without a secure
+    /// random source (see `auth::generate_nonce`) there is no nonce, and no authentication.
+    pub fn forcerenew_nonce(&self, req_packet: &Packet) -> Option<([u8; 16], DhcpOption)> {
+        if !self.config.forcerenew_nonce || !req_packet.forcerenew_nonce_capable() {
+            return None;
+        }
+        let nonce = auth::generate_nonce().ok()?;
+        let option = auth::nonce_option(&nonce, self.next_replay_detection());
+        Some((nonce, option))
+    }
+
+    /// A new reconfigure key for the lease of a client that asked for Reconfigure Key
+    /// authentication with an Authentication option, if the server is configured to hand
+    /// them out. Like a nonce, the key is kept with the lease and added to the DHCPACK, and
+    /// there is none without a secure random source.
+    pub fn reconfigure_key(&self, req_packet: &Packet) -> Option<([u8; 16], DhcpOption)> {
+        if !self.config.reconfigure_key {
+            return None;
+        }
+        match req_packet.option(options::AUTHENTICATION) {
+            Some(DhcpOption::Authentication(a))
+                if a.protocol == options::AUTH_PROTOCOL_RECONFIGURE_KEY =>
+            {
+                let key = auth::generate_nonce().ok()?;
+                let option = auth::reconfigure_key_option(&key, self.next_replay_detection());
+                Some((key, option))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sends a FORCERENEW (RFC 3203) to the client leased `client`, signed with the nonce
+    /// or reconfigure key it was given by `forcerenew_nonce` or `reconfigure_key`.
+    pub fn forcerenew(
+        &self,
+        client: Ipv4Addr,
+        chaddr: HardwareAddr,
+        nonce: &[u8; 16],
+    ) -> std::io::Result<usize> {
+        let message = self.build_forcerenew(client, chaddr, nonce);
+        self.socket
+            .send_to(&message, SocketAddr::new(IpAddr::V4(client), 68))
+    }
+
+    /// The signed message `forcerenew` sends.
+    pub fn build_forcerenew(
+        &self,
+        client: Ipv4Addr,
+        chaddr: HardwareAddr,
+        nonce: &[u8; 16],
+    ) -> Vec<u8> {
+        let p = PacketBuilder::new(chaddr)
+            .reply(true)
+            .xid(builder::random_xid())
+            .ciaddr(client)
+            .message_type(MessageType::ForceRenew)
+            .option(DhcpOption::ServerIdentifier(self.server_ip))
+            .option(auth::unsigned_option(self.next_replay_detection()))
+            .build();
+        let mut out_buf = [0; MIN_MAX_MESSAGE_SIZE as usize];
+        let length = p.encode(&mut out_buf).len();
+        let mut message = out_buf[..length].to_vec();
+        auth::sign(&mut message, nonce);
+        message
+    }
+
+    /// A replay detection value greater than any sent before, from the time of day so that
+    /// it keeps increasing across restarts of the server.
+    fn next_replay_detection(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let mut last = self.replay_detection.load(Ordering::Relaxed);
+        loop {
+            let next = now.max(last + 1);
+            match self.replay_detection.compare_exchange_weak(
+                last,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return next,
+                Err(current) => last = current,
+            }
+        }
+    }
+
+    /// Tells a client that sent Auto-Configure (RFC 2563) that no address is available
+    /// and that it must not configure a link-local address itself.
+    pub fn forbid_auto_configure(&self, req_packet: Packet) -> Result<usize, SendError> {
+        let mut opts = vec![
+            DhcpOption::DhcpMessageType(MessageType::Offer),
+            DhcpOption::ServerIdentifier(
+                req_packet
+                    .server_identifier_override()
+                    .unwrap_or(self.server_ip),
+            ),
+            DhcpOption::AutoConfigure(false),
+        ];
+        echo_relay_agent_information(&mut opts, &req_packet);
+        let max_message_size = req_packet.max_message_size();
+        self.send_with_limit(
+            PacketBuilder::reply_to(&req_packet).options(opts).build(),
+            max_message_size,
+        )
+    }
+
+    /// Copies option 121 to option 249 if so configured and the handler did not supply 249 itself.
+    fn add_legacy_routes(&self, opts: &mut Vec<DhcpOption>) {
+        if !self.config.legacy_classless_static_route
+            || opts
+                .iter()
+                .any(|o| o.code() == options::MS_CLASSLESS_STATIC_ROUTE)
+        {
+            return;
+        }
+        let routes = opts.iter().find_map(|o| match o {
+            DhcpOption::ClasslessStaticRoute(routes) => Some(routes.clone()),
+            _ => None,
+        });
+        if let Some(routes) = routes {
+            opts.push(DhcpOption::MsClasslessStaticRoute(routes));
+        }
+    }
+
+    /// Vendor Specific Information (option 43) carrying `sub_options`, encoded as
+    /// registered for the vendor class (option 60) of the request.
+    pub fn vendor_specific_information(
+        &self,
+        req_packet: &Packet,
+        sub_options: &[options::RawDhcpOption],
+    ) -> Result<DhcpOption, EncodeError> {
+        let class = req_packet.vendor_class().unwrap_or(&[]);
+        self.config.vendors.encode(class, sub_options)
     }
 
     /// Checks the packet see if it was intended for this DHCP server (as opposed to some other also on the network).
+    /// A relay that overrides the server identifier (RFC 5107) stands in for this server.
     pub fn for_this_server(&self, packet: &Packet) -> bool {
-        match packet.option(options::SERVER_IDENTIFIER) {
-            Some(DhcpOption::ServerIdentifier(x)) => x == &self.server_ip,
-            _ => false,
+        match packet.server_identifier() {
+            Some(x) => x == self.server_ip || Some(x) == packet.server_identifier_override(),
+            None => false,
         }
     }
 
     /// Encodes and sends a DHCP packet back to the client.
-    pub fn send(&self, p: Packet) -> std::io::Result<usize> {
+    pub fn send(&self, p: Packet) -> Result<usize, SendError> {
+        self.send_with_limit(p, MIN_MAX_MESSAGE_SIZE)
+    }
+
+    /// Encodes and sends a DHCP packet back to a client that accepts messages of
+    /// up to `max_message_size` octets. Invalid options and those that do not fit are
+    /// left out, and reported in `SendError::OptionsDropped` once the rest is sent.
+    pub fn send_with_limit(&self, p: Packet, max_message_size: u16) -> Result<usize, SendError> {
         let mut addr = self.src;
-        if p.broadcast || addr.ip() == IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)) {
+        if p.broadcast() || addr.ip() == IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)) {
             addr.set_ip(std::net::IpAddr::V4(self.broadcast_ip));
         }
-        println!("Sending Response to: {:?}", addr); // Print the address
 
-        self.socket.send_to(p.encode(&mut self.out_buf.get()), addr)
+        let mut out_buf = vec![0; max_message_size.max(MIN_MAX_MESSAGE_SIZE) as usize];
+        let (length, invalid, too_large) = match p.encode_with_limit(&mut out_buf, max_message_size)
+        {
+            Ok(encoded) => (encoded.len(), Vec::new(), Vec::new()),
+            Err(e) => (e.len, e.invalid, e.codes),
+        };
+        let len = self.socket.send_to(&out_buf[..length], addr)?;
+        if invalid.is_empty() && too_large.is_empty() {
+            Ok(len)
+        } else {
+            Err(SendError::OptionsDropped {
+                len,
+                invalid,
+                too_large,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+impl Server {
+    /// A server on a local socket that sends replies to port 68 of the loopback address.
+    pub(crate) fn for_tests(server_ip: Ipv4Addr, config: ServerConfig) -> Server {
+        Server {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            src: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 68),
+            server_ip,
+            broadcast_ip: Ipv4Addr::BROADCAST,
+            config,
+            replay_detection: AtomicU64::new(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{Ipv4Net, RelayAgentSubOption};
+
+    const SERVER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn server(config: ServerConfig) -> Server {
+        Server::for_tests(SERVER_IP, config)
+    }
+
+    fn request(options: Vec<DhcpOption>) -> Packet {
+        let mut p = Packet::discover(HardwareAddr::ethernet([0, 1, 2, 3, 4, 5]));
+        p.set_option(DhcpOption::DhcpMessageType(MessageType::Request));
+        p.options.extend(options);
+        p
+    }
+
+    fn codes(p: &Packet) -> Vec<u8> {
+        p.options.iter().map(|o| o.code()).collect()
+    }
+
+    fn routes() -> Vec<DhcpOption> {
+        let route = (
+            Ipv4Net::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap(),
+            Ipv4Addr::new(10, 0, 0, 254),
+        );
+        vec![
+            DhcpOption::Router(vec![Ipv4Addr::new(10, 0, 0, 254)]),
+            DhcpOption::ClasslessStaticRoute(vec![route]),
+        ]
+    }
+
+    #[test]
+    fn router_is_sent_with_classless_routes() {
+        let s = server(ServerConfig::default());
+        let req = request(vec![DhcpOption::ParameterRequestList(vec![
+            options::SUBNET_MASK,
+            options::CLASSLESS_ROUTE_FORMAT,
+            options::ROUTER,
+        ])]);
+        let reply = s.build_reply(MessageType::Ack, routes(), Ipv4Addr::new(10, 0, 0, 9), &req);
+        assert_eq!(
+            codes(&reply),
+            vec![
+                options::CLASSLESS_ROUTE_FORMAT,
+                options::ROUTER,
+                options::DHCP_MESSAGE_TYPE,
+                options::SERVER_IDENTIFIER,
+            ]
+        );
+        assert_eq!(reply.yiaddr, Ipv4Addr::new(10, 0, 0, 9));
+    }
+
+    #[test]
+    fn legacy_classless_routes() {
+        let req = request(vec![DhcpOption::ParameterRequestList(vec![
+            options::CLASSLESS_ROUTE_FORMAT,
+            options::MS_CLASSLESS_STATIC_ROUTE,
+        ])]);
+        let plain = server(ServerConfig::default());
+        let reply = plain.build_reply(MessageType::Ack, routes(), Ipv4Addr::UNSPECIFIED, &req);
+        assert!(reply.option(options::MS_CLASSLESS_STATIC_ROUTE).is_none());
+
+        let s = server(ServerConfig {
+            legacy_classless_static_route: true,
+            ..ServerConfig::default()
+        });
+        let reply = s.build_reply(MessageType::Ack, routes(), Ipv4Addr::UNSPECIFIED, &req);
+        match (
+            reply.option(options::CLASSLESS_ROUTE_FORMAT),
+            reply.option(options::MS_CLASSLESS_STATIC_ROUTE),
+        ) {
+            (
+                Some(DhcpOption::ClasslessStaticRoute(routes)),
+                Some(DhcpOption::MsClasslessStaticRoute(legacy)),
+            ) => assert_eq!(routes, legacy),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn relay_agent_information_is_echoed_last() {
+        let relay_info = vec![
+            RelayAgentSubOption::CircuitId(b"eth0/1".to_vec()),
+            RelayAgentSubOption::ServerIdentifierOverride(Ipv4Addr::new(10, 9, 9, 9)),
+        ];
+        let mut req = request(vec![
+            DhcpOption::RelayAgentInformation(relay_info.clone()),
+            DhcpOption::ParameterRequestList(vec![options::SUBNET_MASK]),
+        ]);
+        let s = server(ServerConfig::default());
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![
+                // The handler cannot change what the relay gets back.
+                DhcpOption::RelayAgentInformation(vec![RelayAgentSubOption::RelaySourcePort]),
+                DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+            ],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &req,
+        );
+        assert_eq!(
+            codes(&reply),
+            vec![
+                options::SUBNET_MASK,
+                options::DHCP_MESSAGE_TYPE,
+                options::SERVER_IDENTIFIER,
+                options::RELAY_AGENT_INFORMATION,
+            ]
+        );
+        assert_eq!(
+            reply.option(options::RELAY_AGENT_INFORMATION),
+            Some(&DhcpOption::RelayAgentInformation(relay_info))
+        );
+
+        // The relay stands in for the server, which recognises requests addressed to it.
+        assert_eq!(reply.server_identifier(), Some(Ipv4Addr::new(10, 9, 9, 9)));
+        assert!(!s.for_this_server(&req));
+        req.set_server_identifier(Ipv4Addr::new(10, 9, 9, 9));
+        assert!(s.for_this_server(&req));
+        req.set_server_identifier(SERVER_IP);
+        assert!(s.for_this_server(&req));
+        req.set_server_identifier(Ipv4Addr::new(10, 0, 0, 2));
+        assert!(!s.for_this_server(&req));
+
+        // Without option 82 in the request, none goes back.
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![DhcpOption::RelayAgentInformation(vec![])],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &request(vec![]),
+        );
+        assert!(reply.relay_agent_information().is_none());
+        assert_eq!(reply.server_identifier(), Some(SERVER_IP));
+    }
+
+    #[test]
+    fn captive_portal() {
+        let s = server(ServerConfig {
+            captive_portal: Some("https://portal.example/api".to_string()),
+            ..ServerConfig::default()
+        });
+        let prl = |codes: Vec<u8>| request(vec![DhcpOption::ParameterRequestList(codes)]);
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &prl(vec![options::CAPTIVE_PORTAL]),
+        );
+        assert_eq!(
+            reply.option(options::CAPTIVE_PORTAL),
+            Some(&DhcpOption::CaptivePortal(
+                "https://portal.example/api".to_string()
+            ))
+        );
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &prl(vec![options::SUBNET_MASK]),
+        );
+        assert!(reply.option(options::CAPTIVE_PORTAL).is_none());
+    }
+
+    #[test]
+    fn ipv6_only_preferred() {
+        let s = server(ServerConfig {
+            ipv6_only_wait: Some(60),
+            ..ServerConfig::default()
+        });
+        let offer_ip = Ipv4Addr::new(10, 0, 0, 9);
+        let req = request(vec![DhcpOption::ParameterRequestList(vec![
+            options::IPV6_ONLY_PREFERRED,
+        ])]);
+        assert_eq!(s.ipv6_only_wait(&req), Some(options::MIN_V6ONLY_WAIT));
+
+        let offer = s.build_reply(MessageType::Offer, vec![], offer_ip, &req);
+        assert_eq!(offer.yiaddr, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(
+            offer.option(options::IPV6_ONLY_PREFERRED),
+            Some(&DhcpOption::Ipv6OnlyPreferred(options::MIN_V6ONLY_WAIT))
+        );
+        // Only the offer leaves the address out.
+        let ack = s.build_reply(MessageType::Ack, vec![], offer_ip, &req);
+        assert_eq!(ack.yiaddr, offer_ip);
+        assert!(ack.option(options::IPV6_ONLY_PREFERRED).is_some());
+        let nak = s.build_reply(MessageType::Nak, vec![], Ipv4Addr::UNSPECIFIED, &req);
+        assert!(nak.option(options::IPV6_ONLY_PREFERRED).is_none());
+
+        // Clients that did not ask get an address as usual.
+        let offer = s.build_reply(MessageType::Offer, vec![], offer_ip, &request(vec![]));
+        assert_eq!(offer.yiaddr, offer_ip);
+        assert!(offer.option(options::IPV6_ONLY_PREFERRED).is_none());
+        let plain = server(ServerConfig::default());
+        assert_eq!(plain.ipv6_only_wait(&req), None);
+    }
+
+    #[test]
+    fn rapid_commit() {
+        let s = server(ServerConfig {
+            rapid_commit: true,
+            ..ServerConfig::default()
+        });
+        let mut discover = Packet::discover(HardwareAddr::ethernet([0, 1, 2, 3, 4, 5]));
+        discover.set_parameter_request_list(vec![options::SUBNET_MASK]);
+        assert!(!s.rapid_commit(&discover));
+        discover.set_option(DhcpOption::RapidCommit);
+        assert!(s.rapid_commit(&discover));
+        assert!(!server(ServerConfig::default()).rapid_commit(&discover));
+
+        let ack = s.build_reply(
+            MessageType::Ack,
+            vec![],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &discover,
+        );
+        assert_eq!(ack.message_type(), Ok(MessageType::Ack));
+        assert_eq!(
+            ack.option(options::RAPID_COMMIT),
+            Some(&DhcpOption::RapidCommit)
+        );
+        let offer = s.build_reply(
+            MessageType::Offer,
+            vec![],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &discover,
+        );
+        assert!(offer.option(options::RAPID_COMMIT).is_none());
+
+        // Only a DISCOVER is committed rapidly.
+        let mut req = request(vec![DhcpOption::RapidCommit]);
+        req.set_parameter_request_list(vec![options::SUBNET_MASK]);
+        let ack = s.build_reply(MessageType::Ack, vec![], Ipv4Addr::new(10, 0, 0, 9), &req);
+        assert!(ack.option(options::RAPID_COMMIT).is_none());
+    }
+
+    #[test]
+    fn forcerenew_keys_are_echoed_unrequested() {
+        let s = server(ServerConfig {
+            forcerenew_nonce: true,
+            reconfigure_key: true,
+            ..ServerConfig::default()
+        });
+        let mut req = request(vec![DhcpOption::ParameterRequestList(vec![
+            options::SUBNET_MASK,
+        ])]);
+        assert!(s.forcerenew_nonce(&req).is_none());
+        assert!(s.reconfigure_key(&req).is_none());
+
+        req.set_option(DhcpOption::ForcerenewNonceCapable(vec![
+            options::AUTH_ALGORITHM_HMAC_MD5,
+        ]));
+        let (nonce, option) = s.forcerenew_nonce(&req).unwrap();
+        assert!(server(ServerConfig::default())
+            .forcerenew_nonce(&req)
+            .is_none());
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![
+                option.clone(),
+                DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+            ],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &req,
+        );
+        assert_eq!(
+            codes(&reply),
+            vec![
+                options::SUBNET_MASK,
+                options::DHCP_MESSAGE_TYPE,
+                options::SERVER_IDENTIFIER,
+                options::AUTHENTICATION,
+            ]
+        );
+        assert_eq!(reply.option(options::AUTHENTICATION), Some(&option));
+        match option {
+            DhcpOption::Authentication(a) => assert_eq!(a.information[1..], nonce),
+            other => panic!("{:?}", other),
+        }
+
+        // Clients that send an Authentication option get a reconfigure key instead.
+        let mut req = request(vec![]);
+        req.set_option(DhcpOption::Authentication(options::Authentication {
+            protocol: options::AUTH_PROTOCOL_RECONFIGURE_KEY,
+            algorithm: options::AUTH_ALGORITHM_HMAC_MD5,
+            rdm: options::AUTH_RDM_MONOTONIC,
+            replay_detection: 0,
+            information: vec![],
+        }));
+        assert!(s.forcerenew_nonce(&req).is_none());
+        match s.reconfigure_key(&req) {
+            Some((key, DhcpOption::Authentication(a))) => {
+                assert_eq!(a.protocol, options::AUTH_PROTOCOL_RECONFIGURE_KEY);
+                assert_eq!(a.information[0], options::RECONFIGURE_KEY_VALUE);
+                assert_eq!(a.information[1..], key);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(server(ServerConfig::default())
+            .reconfigure_key(&req)
+            .is_none());
+    }
+
+    #[test]
+    fn forcerenew_is_signed() {
+        let s = server(ServerConfig::default());
+        let client = Ipv4Addr::LOCALHOST;
+        let chaddr = HardwareAddr::ethernet([0, 1, 2, 3, 4, 5]);
+        let key = auth::generate_nonce().unwrap();
+
+        let message = s.build_forcerenew(client, chaddr, &key);
+        let first = auth::verify_fresh(&message, &key, None).unwrap();
+        assert!(!auth::verify(&message, &auth::generate_nonce().unwrap()));
+        let p = Packet::from(&message).unwrap();
+        assert_eq!(p.message_type(), Ok(MessageType::ForceRenew));
+        assert_eq!(p.ciaddr, client);
+        assert_eq!(p.chaddr, chaddr);
+        assert_eq!(p.server_identifier(), Some(SERVER_IP));
+
+        // Each message is newer than the last, so clients never take it for a replay.
+        let message = s.build_forcerenew(client, chaddr, &key);
+        assert!(auth::verify_fresh(&message, &key, Some(first)).is_some());
+
+        assert_eq!(s.forcerenew(client, chaddr, &key).unwrap(), message.len());
+    }
+
+    #[test]
+    fn replay_detection_keeps_increasing() {
+        let s = server(ServerConfig::default());
+        let first = s.next_replay_detection();
+        assert!(s.next_replay_detection() > first);
+
+        // Values already sent ahead of the clock are not repeated.
+        s.replay_detection.store(u64::MAX - 1, Ordering::Relaxed);
+        assert_eq!(s.next_replay_detection(), u64::MAX);
+    }
+
+    #[test]
+    fn dropped_options_are_reported() {
+        let s = server(ServerConfig::default());
+        let req = request(vec![]);
+        let reply = s.build_reply(
+            MessageType::Ack,
+            vec![
+                DhcpOption::Router(vec![]),
+                DhcpOption::DomainNameServer(vec![Ipv4Addr::new(10, 0, 0, 53); 100]),
+            ],
+            Ipv4Addr::new(10, 0, 0, 9),
+            &req,
+        );
+        match s.send_with_limit(reply.clone(), MIN_MAX_MESSAGE_SIZE) {
+            Err(SendError::OptionsDropped {
+                len,
+                invalid,
+                too_large,
+            }) => {
+                assert!(len >= 300);
+                assert_eq!(invalid.len(), 1);
+                assert_eq!(invalid[0].0, options::ROUTER);
+                assert_eq!(too_large, vec![options::DOMAIN_NAME_SERVER]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(s.send_with_limit(reply, 1500).is_err());
+        assert!(s.send(request(vec![])).is_ok());
     }
 }
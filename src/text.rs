@@ -0,0 +1,1004 @@
+//! Options as text, for configuration files and logs: the option's name followed by its
+//! value, such as `routers 10.0.0.1,10.0.0.2` or `domain-name "corp.example"`.
+//!
+//! Names are those of `options::short_name`, though `options::title` is accepted too.
+//! Lists are separated by commas, strings are quoted and opaque data is written as
+//! colon-separated hex. Options without a known type are written `option 224 hex 01:02`.
+//!
+//! `Display` and `FromStr` only know the options the crate decodes itself. Custom options
+//! go by their names with `OptionRegistry::display` and `OptionRegistry::parse`.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::custom::{OptionRegistry, OptionType, OptionValue, NO_DEFINITIONS};
+use crate::options::*;
+use crate::packet::{decode_option_data_with, DecodeErrorKind};
+
+/// Why text could not be read as an option.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseOptionError {
+    /// No option goes by the name the text starts with.
+    UnknownOption(String),
+    /// Part of the value is not of the type the option takes.
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    /// The value was read, but the option does not allow it, such as an empty list.
+    Invalid {
+        option: String,
+        kind: DecodeErrorKind,
+    },
+}
+
+impl fmt::Display for ParseOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOption(name) => write!(f, "unknown option {:?}", name),
+            Self::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(f, "{}: expected {}, found {:?}", option, expected, value),
+            Self::Invalid { option, kind } => write!(f, "{}: {}", option, kind),
+        }
+    }
+}
+
+impl std::error::Error for ParseOptionError {}
+
+/// Name of option `code` in text. Custom options go by their definition's name in lower
+/// case, with hyphens for spaces.
+pub fn option_name(code: u8, registry: &OptionRegistry) -> Option<Cow<'static, str>> {
+    match short_name(code) {
+        Some(name) => Some(Cow::Borrowed(name)),
+        None => registry
+            .definition(code)
+            .map(|d| Cow::Owned(custom_name(&d.name))),
+    }
+}
+
+fn custom_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// How the value of an option is written.
+enum Syntax {
+    Ip,
+    Ips,
+    /// A list of addresses that may be empty.
+    OptionalIps,
+    IpPairs,
+    Routes,
+    U8,
+    U16,
+    U32,
+    I32,
+    U16s,
+    Codes,
+    Bool,
+    Text,
+    /// Quoted if the data is printable text, hex otherwise.
+    Data,
+    Hex,
+    MessageType,
+    Names,
+    Empty,
+    Custom(OptionType),
+}
+
+fn syntax(code: u8, registry: &OptionRegistry) -> Option<Syntax> {
+    Some(match code {
+        SERVER_IDENTIFIER
+        | REQUESTED_IP_ADDRESS
+        | SUBNET_MASK
+        | SWAP_SERVER
+        | BROADCAST_ADDRESS
+        | ROUTER_SOLICITATION_ADDRESS => Syntax::Ip,
+        ROUTER
+        | DOMAIN_NAME_SERVER
+        | TIME_SERVER
+        | NAME_SERVER
+        | LOG_SERVER
+        | COOKIE_SERVER
+        | LPR_SERVER
+        | IMPRESS_SERVER
+        | RESOURCE_LOCATION_SERVER
+        | NETWORK_INFORMATION_SERVERS
+        | NETWORK_TIME_PROTOCOL_SERVERS
+        | NETBIOS_OVER_TCPIP_NAME_SERVER
+        | NETBIOS_OVER_TCPIP_DATAGRAM_DISTRIBUTION_SERVER
+        | XWINDOW_SYSTEM_FONT_SERVER
+        | XWINDOW_SYSTEM_DISPLAY_MANAGER
+        | NETWORK_INFORMATION_SERVICEPLUS_SERVERS
+        | SIMPLE_MAIL_TRANSPORT_PROTOCOL
+        | POST_OFFICE_PROTOCOL_SERVER
+        | NETWORK_NEWS_TRANSPORT_PROTOCOL
+        | DEFAULT_WORLD_WIDE_WEB_SERVER
+        | DEFAULT_FINGER_SERVER
+        | DEFAULT_INTERNET_RELAY_CHAT_SERVER
+        | STREETTALK_SERVER
+        | STREETTALK_DIRECTORY_ASSISTANCE => Syntax::Ips,
+        MOBILE_IP_HOME_AGENT => Syntax::OptionalIps,
+        POLICY_FILTER | STATIC_ROUTE => Syntax::IpPairs,
+        CLASSLESS_ROUTE_FORMAT | MS_CLASSLESS_STATIC_ROUTE => Syntax::Routes,
+        DEFAULT_IP_TIME_TO_LIVE | TCP_DEFAULT_TTL | NETBIOS_OVER_TCPIP_NODE_TYPE | OVERLOAD => {
+            Syntax::U8
+        }
+        MAXIMUM_DHCP_MESSAGE_SIZE
+        | BOOT_FILE_SIZE
+        | MAXIMUM_DATAGRAM_REASSEMBLY_SIZE
+        | INTERFACE_MTU => Syntax::U16,
+        IP_ADDRESS_LEASE_TIME
+        | PATH_MTU_AGING_TIMEOUT
+        | ARP_CACHE_TIMEOUT
+        | TCP_KEEPALIVE_INTERVAL
+        | RENEWAL_TIME_VALUE
+        | REBINDING_TIME_VALUE
+        | IPV6_ONLY_PREFERRED => Syntax::U32,
+        TIME_OFFSET => Syntax::I32,
+        PATH_MTU_PLATEAU_TABLE | CLIENT_ARCHITECTURE => Syntax::U16s,
+        PARAMETER_REQUEST_LIST | FORCERENEW_NONCE_CAPABLE => Syntax::Codes,
+        IP_FORWARDING_ENABLE_DISABLE
+        | NON_LOCAL_SOURCE_ROUTING_ENABLE_DISABLE
+        | ALL_SUBNETS_ARE_LOCAL
+        | PERFORM_MASK_DISCOVERY
+        | MASK_SUPPLIER
+        | PERFORM_ROUTER_DISCOVERY
+        | TRAILER_ENCAPSULATION
+        | ETHERNET_ENCAPSULATION
+        | TCP_KEEPALIVE_GARBAGE
+        | AUTO_CONFIGURE => Syntax::Bool,
+        HOST_NAME
+        | MESSAGE
+        | MERIT_DUMP_FILE
+        | DOMAIN_NAME
+        | ROOT_PATH
+        | EXTENSIONS_PATH
+        | NETWORK_INFORMATION_SERVICE_DOMAIN
+        | NETBIOS_OVER_TCPIP_SCOPE
+        | NETWORK_INFORMATION_SERVICEPLUS_DOMAIN
+        | TFTP_SERVER_NAME
+        | BOOTFILE_NAME
+        | TZ_POSIX_STRING
+        | TZ_DATABASE_STRING
+        | CAPTIVE_PORTAL => Syntax::Text,
+        VENDOR_CLASS_IDENTIFIER => Syntax::Data,
+        VENDOR_SPECIFIC_INFORMATION
+        | RELAY_AGENT_INFORMATION
+        | CLIENT_IDENTIFIER
+        | CLIENT_FQDN
+        | AUTHENTICATION
+        | USER_CLASS
+        | V_I_VENDOR_CLASS
+        | V_I_VENDOR_SPECIFIC_INFORMATION => Syntax::Hex,
+        DHCP_MESSAGE_TYPE => Syntax::MessageType,
+        DOMAIN_SEARCH => Syntax::Names,
+        RAPID_COMMIT => Syntax::Empty,
+        _ => Syntax::Custom(registry.definition(code)?.option_type.clone()),
+    })
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_list<T, F>(f: &mut fmt::Formatter, items: &[T], write: F) -> fmt::Result
+where
+    F: Fn(&mut fmt::Formatter, &T) -> fmt::Result,
+{
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+        write(f, item)?;
+    }
+    Ok(())
+}
+
+/// Writes the option as its name and value, which `str::parse` reads back.
+impl fmt::Display for DhcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        NO_DEFINITIONS.display(self).fmt(f)
+    }
+}
+
+/// An option written with the names of custom options, by `OptionRegistry::display`.
+pub struct OptionDisplay<'a> {
+    option: &'a DhcpOption,
+    registry: &'a OptionRegistry,
+}
+
+impl OptionRegistry {
+    /// Writes `option` as `Display` does, custom options by the name they were defined with.
+    pub fn display<'a>(&'a self, option: &'a DhcpOption) -> OptionDisplay<'a> {
+        OptionDisplay {
+            option,
+            registry: self,
+        }
+    }
+
+    /// Reads an option as `FromStr` does, custom options by the name they were defined with.
+    pub fn parse(&self, s: &str) -> Result<DhcpOption, ParseOptionError> {
+        parse_option(s, self)
+    }
+}
+
+impl fmt::Display for OptionDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.option {
+            DhcpOption::Unrecognized(_) => None,
+            option => option_name(option.code(), self.registry),
+        };
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let raw = self.option.to_raw();
+                return match raw.data.is_empty() {
+                    true => write!(f, "option {} hex", raw.code),
+                    false => write!(f, "option {} hex {}", raw.code, Hex(&raw.data)),
+                };
+            }
+        };
+        f.write_str(&name)?;
+        // Rapid Commit and an empty list of home agents have no value.
+        if self.option.to_raw().data.is_empty() {
+            return Ok(());
+        }
+        f.write_str(" ")?;
+        match self.option {
+            DhcpOption::DhcpMessageType(mtype) => match mtype {
+                MessageType::Other(val) => write!(f, "{}", val),
+                _ => write!(f, "{}", mtype),
+            },
+            DhcpOption::ServerIdentifier(x)
+            | DhcpOption::RequestedIpAddress(x)
+            | DhcpOption::SubnetMask(x)
+            | DhcpOption::SwapServer(x)
+            | DhcpOption::BroadcastAddress(x)
+            | DhcpOption::RouterSolicitationAddress(x) => write!(f, "{}", x),
+            DhcpOption::Router(x)
+            | DhcpOption::DomainNameServer(x)
+            | DhcpOption::TimeServer(x)
+            | DhcpOption::NameServer(x)
+            | DhcpOption::LogServer(x)
+            | DhcpOption::CookieServer(x)
+            | DhcpOption::LprServer(x)
+            | DhcpOption::ImpressServer(x)
+            | DhcpOption::ResourceLocationServer(x)
+            | DhcpOption::NetworkInformationServers(x)
+            | DhcpOption::NetworkTimeProtocolServers(x)
+            | DhcpOption::NetbiosNameServer(x)
+            | DhcpOption::NetbiosDatagramDistributionServer(x)
+            | DhcpOption::XWindowSystemFontServer(x)
+            | DhcpOption::XWindowSystemDisplayManager(x)
+            | DhcpOption::NetworkInformationServicePlusServers(x)
+            | DhcpOption::MobileIpHomeAgent(x)
+            | DhcpOption::SmtpServer(x)
+            | DhcpOption::Pop3Server(x)
+            | DhcpOption::NntpServer(x)
+            | DhcpOption::WwwServer(x)
+            | DhcpOption::FingerServer(x)
+            | DhcpOption::IrcServer(x)
+            | DhcpOption::StreetTalkServer(x)
+            | DhcpOption::StreetTalkDirectoryAssistanceServer(x) => {
+                write_list(f, x, |f, a| write!(f, "{}", a))
+            }
+            DhcpOption::PolicyFilter(x) | DhcpOption::StaticRoute(x) => {
+                write_list(f, x, |f, (a, b)| write!(f, "{} {}", a, b))
+            }
+            DhcpOption::ClasslessStaticRoute(x) | DhcpOption::MsClasslessStaticRoute(x) => {
+                write_list(f, x, |f, (net, router)| write!(f, "{} {}", net, router))
+            }
+            DhcpOption::DefaultIpTimeToLive(x)
+            | DhcpOption::TcpDefaultTtl(x)
+            | DhcpOption::NetbiosNodeType(x)
+            | DhcpOption::Overload(x) => write!(f, "{}", x),
+            DhcpOption::MaximumDhcpMessageSize(x)
+            | DhcpOption::BootFileSize(x)
+            | DhcpOption::MaximumDatagramReassemblySize(x)
+            | DhcpOption::InterfaceMtu(x) => write!(f, "{}", x),
+            DhcpOption::IpAddressLeaseTime(x)
+            | DhcpOption::PathMtuAgingTimeout(x)
+            | DhcpOption::ArpCacheTimeout(x)
+            | DhcpOption::TcpKeepaliveInterval(x)
+            | DhcpOption::RenewalTimeValue(x)
+            | DhcpOption::RebindingTimeValue(x)
+            | DhcpOption::Ipv6OnlyPreferred(x) => write!(f, "{}", x),
+            DhcpOption::TimeOffset(x) => write!(f, "{}", x),
+            DhcpOption::PathMtuPlateauTable(x) | DhcpOption::ClientArchitecture(x) => {
+                write_list(f, x, |f, n| write!(f, "{}", n))
+            }
+            DhcpOption::ParameterRequestList(x) | DhcpOption::ForcerenewNonceCapable(x) => {
+                write_list(f, x, |f, n| write!(f, "{}", n))
+            }
+            DhcpOption::IpForwarding(x)
+            | DhcpOption::NonLocalSourceRouting(x)
+            | DhcpOption::AllSubnetsAreLocal(x)
+            | DhcpOption::PerformMaskDiscovery(x)
+            | DhcpOption::MaskSupplier(x)
+            | DhcpOption::PerformRouterDiscovery(x)
+            | DhcpOption::TrailerEncapsulation(x)
+            | DhcpOption::EthernetEncapsulation(x)
+            | DhcpOption::TcpKeepaliveGarbage(x)
+            | DhcpOption::AutoConfigure(x) => write!(f, "{}", x),
+            DhcpOption::HostName(x)
+            | DhcpOption::Message(x)
+            | DhcpOption::MeritDumpFile(x)
+            | DhcpOption::DomainName(x)
+            | DhcpOption::RootPath(x)
+            | DhcpOption::ExtensionsPath(x)
+            | DhcpOption::NetworkInformationServiceDomain(x)
+            | DhcpOption::NetbiosScope(x)
+            | DhcpOption::NetworkInformationServicePlusDomain(x)
+            | DhcpOption::TftpServerName(x)
+            | DhcpOption::BootfileName(x)
+            | DhcpOption::TzPosixString(x)
+            | DhcpOption::TzDatabaseString(x)
+            | DhcpOption::CaptivePortal(x) => write!(f, "{:?}", x),
+            DhcpOption::VendorClassIdentifier(x) => match std::str::from_utf8(x) {
+                Ok(s) if !s.chars().any(char::is_control) => write!(f, "{:?}", s),
+                _ => write!(f, "{}", Hex(x)),
+            },
+            DhcpOption::DomainSearch(x) => write_list(f, x, |f, name| write!(f, "{:?}", name)),
+            DhcpOption::Custom(_, value) => write!(f, "{}", value),
+            DhcpOption::VendorSpecificInformation(_)
+            | DhcpOption::RelayAgentInformation(_)
+            | DhcpOption::ClientIdentifier(_)
+            | DhcpOption::ClientFqdn(_)
+            | DhcpOption::Authentication(_)
+            | DhcpOption::UserClass(_)
+            | DhcpOption::ViVendorClass(_)
+            | DhcpOption::ViVendorSpecificInformation(_)
+            | DhcpOption::RapidCommit
+            | DhcpOption::Unrecognized(_) => write!(f, "{}", Hex(&self.option.to_raw().data)),
+        }
+    }
+}
+
+/// A part of the value that is not what was expected, and what was.
+type Expected<'a> = (&'a str, &'static str);
+
+/// Reads an option written as `Display` writes it, or named by its title.
+impl FromStr for DhcpOption {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<DhcpOption, ParseOptionError> {
+        parse_option(s, &NO_DEFINITIONS)
+    }
+}
+
+fn parse_option(s: &str, registry: &OptionRegistry) -> Result<DhcpOption, ParseOptionError> {
+    let s = s.trim();
+    let (code, name, value) = match split_token(s) {
+        ("option", rest) => {
+            let (code, value) = split_token(rest);
+            match code.parse::<u8>() {
+                Ok(code) => (code, format!("option {}", code), value),
+                Err(_) => {
+                    return Err(ParseOptionError::InvalidValue {
+                        option: "option".to_string(),
+                        value: code.to_string(),
+                        expected: "an option code from 0 to 255",
+                    })
+                }
+            }
+        }
+        _ => match find_name(s, registry) {
+            Some((code, len)) => (code, s[..len].to_string(), s[len..].trim()),
+            None => return Err(ParseOptionError::UnknownOption(split_token(s).0.into())),
+        },
+    };
+
+    let data = match (split_token(value), syntax(code, registry)) {
+        (("hex", hex), _) if name.starts_with("option ") => {
+            let data = parse_hex(hex).map_err(|e| invalid(&name, e))?;
+            return Ok(DhcpOption::Unrecognized(RawDhcpOption { code, data }));
+        }
+        (_, Some(syntax)) => parse_data(&syntax, value).map_err(|e| invalid(&name, e))?,
+        (_, None) => {
+            return Err(ParseOptionError::InvalidValue {
+                option: name,
+                value: value.to_string(),
+                expected: "hex data for an option of unknown type",
+            })
+        }
+    };
+    decode_option_data_with(code, &data, registry)
+        .map_err(|kind| ParseOptionError::Invalid { option: name, kind })
+}
+
+fn invalid(option: &str, (value, expected): Expected) -> ParseOptionError {
+    ParseOptionError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+        expected,
+    }
+}
+
+/// The first word of `s`, and the rest without leading whitespace.
+fn split_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(end) => (&s[..end], s[end..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+/// Codes of the built-in options by name and title in lower case, built on first use.
+fn builtin_names() -> &'static HashMap<String, u8> {
+    static NAMES: OnceLock<HashMap<String, u8>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        let mut names = HashMap::new();
+        for code in 1..=254 {
+            for name in short_name(code).into_iter().chain(title(code)) {
+                names.entry(name.to_ascii_lowercase()).or_insert(code);
+            }
+        }
+        names
+    })
+}
+
+/// Code and length of the longest option name or title that `s` starts with.
+fn find_name(s: &str, registry: &OptionRegistry) -> Option<(u8, usize)> {
+    let ends: Vec<usize> = s
+        .char_indices()
+        .filter(|(_, c)| c.is_whitespace())
+        .map(|(i, _)| i)
+        .chain(Some(s.len()))
+        .collect();
+    for &end in ends.iter().rev() {
+        let prefix = s[..end].to_ascii_lowercase();
+        if let Some(&code) = builtin_names().get(&prefix) {
+            return Some((code, end));
+        }
+        let custom = registry
+            .definitions()
+            .find(|d| d.name.eq_ignore_ascii_case(&prefix) || custom_name(&d.name) == prefix);
+        if let Some(definition) = custom {
+            return Some((definition.code, end));
+        }
+    }
+    None
+}
+
+/// Splits a list at the commas outside of quotes.
+fn split_list(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(s[start..].trim());
+    items
+}
+
+fn parse_list<'a, T>(
+    s: &'a str,
+    expected: &'static str,
+    parse: impl Fn(&'a str) -> Result<T, Expected<'a>>,
+) -> Result<Vec<T>, Expected<'a>> {
+    if s.is_empty() {
+        return Err((s, expected));
+    }
+    split_list(s).into_iter().map(parse).collect()
+}
+
+fn parse_ip(s: &str) -> Result<Ipv4Addr, Expected<'_>> {
+    s.parse().map_err(|_| (s, "an IPv4 address"))
+}
+
+fn parse_number<'a, T: FromStr>(s: &'a str, expected: &'static str) -> Result<T, Expected<'a>> {
+    s.parse().map_err(|_| (s, expected))
+}
+
+fn parse_bool(s: &str) -> Result<bool, Expected<'_>> {
+    match s {
+        "true" | "on" => Ok(true),
+        "false" | "off" => Ok(false),
+        _ => Err((s, "true or false")),
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, Expected<'_>> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(':')
+        .map(|b| match b.len() {
+            1 | 2 => u8::from_str_radix(b, 16).map_err(|_| (s, "colon-separated hex octets")),
+            _ => Err((s, "colon-separated hex octets")),
+        })
+        .collect()
+}
+
+/// Reads a string as `{:?}` writes it. Text without quotes is taken as it is.
+fn parse_text(s: &str) -> Result<String, Expected<'_>> {
+    let inner = match s.strip_prefix('"') {
+        Some(rest) => match rest.strip_suffix('"') {
+            Some(inner) => inner,
+            None => return Err((s, "a closing quote")),
+        },
+        None => return Ok(s.to_string()),
+    };
+    let bad_escape = (s, "a string with valid escapes");
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            return Err((s, "quotes inside a string to be escaped"));
+        }
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        text.push(match chars.next().ok_or(bad_escape)? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '"' | '\'') => c,
+            'u' => {
+                let rest = chars.as_str();
+                let end = rest.find('}').ok_or(bad_escape)?;
+                let hex = rest[..end].strip_prefix('{').ok_or(bad_escape)?;
+                let c = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(bad_escape)?;
+                chars = rest[end + 1..].chars();
+                c
+            }
+            _ => return Err(bad_escape),
+        });
+    }
+    Ok(text)
+}
+
+/// A quoted name for option 119, which `encode_domain_search` can write as it is.
+fn parse_domain_name(s: &str) -> Result<String, Expected<'_>> {
+    match parse_text(s)? {
+        name if is_domain_name(&name) => Ok(name),
+        _ => Err((s, "a domain name")),
+    }
+}
+
+fn parse_message_type(s: &str) -> Result<MessageType, Expected<'_>> {
+    if let Ok(val) = s.parse::<u8>() {
+        return MessageType::try_from(val).map_err(|_| (s, "a DHCP message type"));
+    }
+    let upper = s.to_ascii_uppercase();
+    let name = upper.strip_prefix("DHCP").unwrap_or(&upper);
+    (1..=18)
+        .filter_map(|val| MessageType::try_from(val).ok())
+        .find(|mtype| mtype.to_string()[4..] == *name)
+        .ok_or((s, "a DHCP message type"))
+}
+
+/// Reads a value of `syntax` into the option's data, for `decode_option_data` to check.
+fn parse_data<'a>(syntax: &Syntax, s: &'a str) -> Result<Vec<u8>, Expected<'a>> {
+    Ok(match syntax {
+        Syntax::Ip => parse_ip(s)?.octets().to_vec(),
+        Syntax::OptionalIps if s.is_empty() => Vec::new(),
+        Syntax::Ips | Syntax::OptionalIps => parse_list(s, "IPv4 addresses", parse_ip)?
+            .iter()
+            .flat_map(|a| a.octets())
+            .collect(),
+        Syntax::IpPairs => parse_list(s, "pairs of IPv4 addresses", |pair| {
+            match split_token(pair) {
+                (a, b) if !b.is_empty() => Ok([parse_ip(a)?.octets(), parse_ip(b)?.octets()]),
+                _ => Err((pair, "a pair of IPv4 addresses")),
+            }
+        })?
+        .concat()
+        .concat(),
+        Syntax::Routes => {
+            let routes = parse_list(s, "routes", |route| {
+                let (dest, router) = split_token(route);
+                let net = dest
+                    .split_once('/')
+                    .and_then(|(addr, len)| Ipv4Net::new(addr.parse().ok()?, len.parse().ok()?))
+                    .ok_or((dest, "a destination such as 10.0.0.0/8"))?;
+                Ok((net, parse_ip(router)?))
+            })?;
+            DhcpOption::ClasslessStaticRoute(routes).to_raw().data
+        }
+        Syntax::U8 => vec![parse_number::<u8>(s, "a number from 0 to 255")?],
+        Syntax::U16 => parse_number::<u16>(s, "a number from 0 to 65535")?
+            .to_be_bytes()
+            .to_vec(),
+        Syntax::U32 => parse_number::<u32>(s, "a number from 0 to 4294967295")?
+            .to_be_bytes()
+            .to_vec(),
+        Syntax::I32 => parse_number::<i32>(s, "a signed 32-bit number")?
+            .to_be_bytes()
+            .to_vec(),
+        Syntax::U16s => parse_list(s, "numbers", |n| {
+            parse_number::<u16>(n, "a number from 0 to 65535")
+        })?
+        .iter()
+        .flat_map(|n| n.to_be_bytes())
+        .collect(),
+        Syntax::Codes => parse_list(s, "option codes", |n| {
+            parse_number::<u8>(n, "an option code from 0 to 255")
+        })?,
+        Syntax::Bool => vec![parse_bool(s)? as u8],
+        Syntax::Text => parse_text(s)?.into_bytes(),
+        Syntax::Data if s.starts_with('"') => parse_text(s)?.into_bytes(),
+        Syntax::Data | Syntax::Hex => parse_hex(s)?,
+        Syntax::MessageType => vec![u8::from(parse_message_type(s)?)],
+        Syntax::Names => {
+            let names = parse_list(s, "domain names", parse_domain_name)?;
+            encode_domain_search(&names)
+        }
+        Syntax::Empty if s.is_empty() => Vec::new(),
+        Syntax::Empty => return Err((s, "no value")),
+        Syntax::Custom(option_type) => match parse_value(option_type, s)? {
+            (value, "") => value.to_bytes(),
+            (_, rest) => return Err((rest, "the end of the value")),
+        },
+    })
+}
+
+/// Reads a custom option value as `OptionValue`'s `Display` writes it. Fields of a fixed
+/// length are a word each; any other field takes the rest of the text.
+fn parse_value<'a>(
+    option_type: &OptionType,
+    s: &'a str,
+) -> Result<(OptionValue, &'a str), Expected<'a>> {
+    let (word, rest) = split_token(s);
+    Ok(match option_type {
+        OptionType::Ip => (OptionValue::Ip(parse_ip(word)?), rest),
+        OptionType::U8 => (
+            OptionValue::U8(parse_number(word, "a number from 0 to 255")?),
+            rest,
+        ),
+        OptionType::U16 => (
+            OptionValue::U16(parse_number(word, "a number from 0 to 65535")?),
+            rest,
+        ),
+        OptionType::U32 => (
+            OptionValue::U32(parse_number(word, "a number from 0 to 4294967295")?),
+            rest,
+        ),
+        OptionType::Bool => (OptionValue::Bool(parse_bool(word)?), rest),
+        OptionType::IpArray => (
+            OptionValue::IpArray(parse_list(s, "IPv4 addresses", parse_ip)?),
+            "",
+        ),
+        OptionType::String => (OptionValue::String(parse_text(s.trim())?), ""),
+        OptionType::Bytes => (OptionValue::Bytes(parse_hex(s.trim())?), ""),
+        OptionType::Record(fields) => {
+            let mut values = Vec::with_capacity(fields.len());
+            let mut rest = s;
+            for field in fields {
+                let (value, r) = parse_value(field, rest)?;
+                values.push(value);
+                rest = r;
+            }
+            (OptionValue::Record(values), rest)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{HardwareAddr, Packet};
+
+    fn round_trip(option: DhcpOption) -> String {
+        let text = option.to_string();
+        assert_eq!(text.parse::<DhcpOption>(), Ok(option), "{}", text);
+        text
+    }
+
+    #[test]
+    fn options_as_text() {
+        let a = Ipv4Addr::new(10, 0, 0, 1);
+        let b = Ipv4Addr::new(10, 0, 0, 2);
+        assert_eq!(
+            round_trip(DhcpOption::Router(vec![a, b])),
+            "routers 10.0.0.1,10.0.0.2"
+        );
+        assert_eq!(
+            round_trip(DhcpOption::DomainName("corp.example".to_string())),
+            r#"domain-name "corp.example""#
+        );
+        assert_eq!(
+            round_trip(DhcpOption::DhcpMessageType(MessageType::Discover)),
+            "dhcp-message-type DHCPDISCOVER"
+        );
+        assert_eq!(round_trip(DhcpOption::RapidCommit), "rapid-commit");
+        assert_eq!(
+            round_trip(DhcpOption::Unrecognized(RawDhcpOption {
+                code: 224,
+                data: vec![1, 2],
+            })),
+            "option 224 hex 01:02"
+        );
+        assert_eq!(
+            round_trip(DhcpOption::ClasslessStaticRoute(vec![(
+                Ipv4Net::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap(),
+                a
+            )])),
+            "classless-static-routes 10.0.0.0/8 10.0.0.1"
+        );
+        assert_eq!(
+            round_trip(DhcpOption::VendorClassIdentifier(b"MSFT 5.0".to_vec())),
+            r#"vendor-class-identifier "MSFT 5.0""#
+        );
+
+        let mut net = Ipv4Net::new(Ipv4Addr::new(192, 168, 0, 0), 16).unwrap();
+        let options = vec![
+            DhcpOption::DhcpMessageType(MessageType::Other(42)),
+            DhcpOption::TimeOffset(-3600),
+            DhcpOption::StaticRoute(vec![(a, b), (b, a)]),
+            DhcpOption::PathMtuPlateauTable(vec![1500, 576]),
+            DhcpOption::ParameterRequestList(vec![1, 3, 6, 15]),
+            DhcpOption::IpForwarding(true),
+            DhcpOption::Message("line\n\"quoted\", \u{7f} and \\".to_string()),
+            DhcpOption::VendorClassIdentifier(vec![0, 0xff]),
+            DhcpOption::ClientIdentifier(ClientIdentifier::HardwareAddr {
+                htype: 1,
+                addr: vec![0, 0x0b, 0x82, 1, 0xfc, 0x42],
+            }),
+            DhcpOption::ClientFqdn(ClientFqdn::new("host.example.").unwrap()),
+            DhcpOption::DomainSearch(vec!["a.example".to_string(), "b.corp.example".to_string()]),
+            DhcpOption::UserClass(vec![b"iPXE".to_vec()]),
+            DhcpOption::RelayAgentInformation(vec![RelayAgentSubOption::CircuitId(vec![1])]),
+            DhcpOption::MsClasslessStaticRoute(vec![(net, a), {
+                net = Ipv4Net::new(Ipv4Addr::UNSPECIFIED, 0).unwrap();
+                (net, b)
+            }]),
+            DhcpOption::Ipv6OnlyPreferred(1800),
+            DhcpOption::MaximumDhcpMessageSize(1500),
+            DhcpOption::NetbiosNodeType(8),
+        ];
+        for option in options {
+            round_trip(option);
+        }
+    }
+
+    #[test]
+    fn every_option_as_text() {
+        let a = Ipv4Addr::new(10, 0, 0, 1);
+        let b = Ipv4Addr::new(10, 0, 0, 2);
+        let net = Ipv4Net::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap();
+        let text = |s: &str| s.to_string();
+        let options = vec![
+            DhcpOption::DhcpMessageType(MessageType::Ack),
+            DhcpOption::ServerIdentifier(a),
+            DhcpOption::ParameterRequestList(vec![1, 3]),
+            DhcpOption::RequestedIpAddress(a),
+            DhcpOption::HostName(text("web")),
+            DhcpOption::Router(vec![a]),
+            DhcpOption::DomainNameServer(vec![a, b]),
+            DhcpOption::IpAddressLeaseTime(3600),
+            DhcpOption::MaximumDhcpMessageSize(1500),
+            DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+            DhcpOption::Message(text("no free leases")),
+            DhcpOption::TimeOffset(7200),
+            DhcpOption::TimeServer(vec![a]),
+            DhcpOption::NameServer(vec![a]),
+            DhcpOption::LogServer(vec![a]),
+            DhcpOption::CookieServer(vec![a]),
+            DhcpOption::LprServer(vec![a]),
+            DhcpOption::ImpressServer(vec![a]),
+            DhcpOption::ResourceLocationServer(vec![a]),
+            DhcpOption::BootFileSize(64),
+            DhcpOption::MeritDumpFile(text("/var/dump")),
+            DhcpOption::DomainName(text("example")),
+            DhcpOption::SwapServer(a),
+            DhcpOption::RootPath(text("/export/root")),
+            DhcpOption::ExtensionsPath(text("/ext")),
+            DhcpOption::IpForwarding(false),
+            DhcpOption::NonLocalSourceRouting(true),
+            DhcpOption::PolicyFilter(vec![(a, Ipv4Addr::new(255, 0, 0, 0))]),
+            DhcpOption::MaximumDatagramReassemblySize(576),
+            DhcpOption::DefaultIpTimeToLive(64),
+            DhcpOption::PathMtuAgingTimeout(600),
+            DhcpOption::PathMtuPlateauTable(vec![68, 1500]),
+            DhcpOption::InterfaceMtu(9000),
+            DhcpOption::AllSubnetsAreLocal(true),
+            DhcpOption::BroadcastAddress(Ipv4Addr::new(10, 0, 0, 255)),
+            DhcpOption::PerformMaskDiscovery(false),
+            DhcpOption::MaskSupplier(true),
+            DhcpOption::PerformRouterDiscovery(false),
+            DhcpOption::RouterSolicitationAddress(Ipv4Addr::new(224, 0, 0, 2)),
+            DhcpOption::StaticRoute(vec![(a, b)]),
+            DhcpOption::TrailerEncapsulation(true),
+            DhcpOption::ArpCacheTimeout(60),
+            DhcpOption::EthernetEncapsulation(false),
+            DhcpOption::TcpDefaultTtl(128),
+            DhcpOption::TcpKeepaliveInterval(7200),
+            DhcpOption::TcpKeepaliveGarbage(true),
+            DhcpOption::NetworkInformationServiceDomain(text("nis")),
+            DhcpOption::NetworkInformationServers(vec![a]),
+            DhcpOption::NetworkTimeProtocolServers(vec![a, b]),
+            DhcpOption::VendorSpecificInformation(vec![1, 2, 0xab, 0xcd]),
+            DhcpOption::NetbiosNameServer(vec![a]),
+            DhcpOption::NetbiosDatagramDistributionServer(vec![a]),
+            DhcpOption::NetbiosNodeType(2),
+            DhcpOption::NetbiosScope(text("scope")),
+            DhcpOption::XWindowSystemFontServer(vec![a]),
+            DhcpOption::XWindowSystemDisplayManager(vec![a]),
+            DhcpOption::NetworkInformationServicePlusDomain(text("nisplus")),
+            DhcpOption::NetworkInformationServicePlusServers(vec![a]),
+            DhcpOption::MobileIpHomeAgent(vec![]),
+            DhcpOption::SmtpServer(vec![a]),
+            DhcpOption::Pop3Server(vec![a]),
+            DhcpOption::NntpServer(vec![a]),
+            DhcpOption::WwwServer(vec![a]),
+            DhcpOption::FingerServer(vec![a]),
+            DhcpOption::IrcServer(vec![a]),
+            DhcpOption::StreetTalkServer(vec![a]),
+            DhcpOption::StreetTalkDirectoryAssistanceServer(vec![a]),
+            DhcpOption::RelayAgentInformation(vec![
+                RelayAgentSubOption::RemoteId(b"port 7".to_vec()),
+                RelayAgentSubOption::LinkSelection(a),
+                RelayAgentSubOption::SubscriberId(text("sub")),
+                RelayAgentSubOption::RelayAgentFlags(0x80),
+                RelayAgentSubOption::ServerIdentifierOverride(b),
+                RelayAgentSubOption::RelaySourcePort,
+            ]),
+            DhcpOption::Overload(3),
+            DhcpOption::RenewalTimeValue(1800),
+            DhcpOption::RebindingTimeValue(3150),
+            DhcpOption::VendorClassIdentifier(b"PXEClient".to_vec()),
+            DhcpOption::ClientIdentifier(ClientIdentifier::Opaque(b"client".to_vec())),
+            DhcpOption::ClientFqdn(ClientFqdn::new("host").unwrap()),
+            DhcpOption::DomainSearch(vec![text("example")]),
+            DhcpOption::RapidCommit,
+            DhcpOption::Ipv6OnlyPreferred(300),
+            DhcpOption::CaptivePortal(text("https://portal.example/api")),
+            DhcpOption::AutoConfigure(true),
+            DhcpOption::Authentication(Authentication {
+                protocol: AUTH_PROTOCOL_RECONFIGURE_KEY,
+                algorithm: AUTH_ALGORITHM_HMAC_MD5,
+                rdm: AUTH_RDM_MONOTONIC,
+                replay_detection: 7,
+                information: vec![1; 17],
+            }),
+            DhcpOption::ForcerenewNonceCapable(vec![AUTH_ALGORITHM_HMAC_MD5]),
+            DhcpOption::ViVendorClass(vec![(3561, vec![b"class".to_vec()])]),
+            DhcpOption::ViVendorSpecificInformation(vec![(
+                3561,
+                vec![RawDhcpOption {
+                    code: 1,
+                    data: vec![5],
+                }],
+            )]),
+            DhcpOption::TftpServerName(text("tftp.example")),
+            DhcpOption::BootfileName(text("pxelinux.0")),
+            DhcpOption::UserClass(vec![b"a".to_vec(), b"bc".to_vec()]),
+            DhcpOption::ClientArchitecture(vec![7]),
+            DhcpOption::TzPosixString(text("CET-1CEST,M3.5.0,M10.5.0/3")),
+            DhcpOption::TzDatabaseString(text("Europe/Amsterdam")),
+            DhcpOption::ClasslessStaticRoute(vec![(net, a)]),
+            DhcpOption::MsClasslessStaticRoute(vec![(net, b)]),
+        ];
+        let mut codes: Vec<u8> = options.iter().map(DhcpOption::code).collect();
+        for option in options {
+            round_trip(option);
+        }
+
+        // Every option the crate decodes has a case above.
+        codes.sort_unstable();
+        codes.dedup();
+        let builtin: Vec<u8> = (1..=254).filter(|&code| title(code).is_some()).collect();
+        assert_eq!(codes, builtin);
+    }
+
+    #[test]
+    fn titles_and_errors() {
+        let p = Packet::discover(HardwareAddr::ethernet([0; 6]));
+        assert_eq!(
+            p.options[0].to_string().to_uppercase(),
+            "DHCP-MESSAGE-TYPE DHCPDISCOVER"
+        );
+        assert_eq!(
+            "Domain Name Server 8.8.8.8".parse(),
+            Ok(DhcpOption::DomainNameServer(vec![Ipv4Addr::new(
+                8, 8, 8, 8
+            )]))
+        );
+        assert_eq!(
+            "dhcp-message-type request".parse(),
+            Ok(DhcpOption::DhcpMessageType(MessageType::Request))
+        );
+        assert_eq!(
+            "host-name web".parse::<DhcpOption>().unwrap().code(),
+            HOST_NAME
+        );
+
+        let error = |s: &str| s.parse::<DhcpOption>().unwrap_err().to_string();
+        assert_eq!(error("gateway 10.0.0.1"), r#"unknown option "gateway""#);
+        assert_eq!(
+            error("routers 10.0.0.1,10.0.0"),
+            r#"routers: expected an IPv4 address, found "10.0.0""#
+        );
+        assert_eq!(
+            error("interface-mtu 40"),
+            "interface-mtu: invalid option value"
+        );
+        assert_eq!(
+            error("routers"),
+            r#"routers: expected IPv4 addresses, found """#
+        );
+        assert_eq!(
+            error(r#"domain-search "corp.example","a..b""#),
+            r#"domain-search: expected a domain name, found "\"a..b\"""#
+        );
+        assert_eq!(
+            error("option 224 01:02"),
+            r#"option 224: expected hex data for an option of unknown type, found "01:02""#
+        );
+    }
+
+    #[test]
+    fn custom_options_as_text() {
+        let mut registry = OptionRegistry::new();
+        let record =
+            OptionType::Record(vec![OptionType::Ip, OptionType::Bool, OptionType::IpArray]);
+        registry.define(241, "Backup Servers", record).unwrap();
+        let option = DhcpOption::Custom(
+            241,
+            OptionValue::Record(vec![
+                OptionValue::Ip(Ipv4Addr::new(10, 0, 0, 1)),
+                OptionValue::Bool(false),
+                OptionValue::IpArray(vec![Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 3)]),
+            ]),
+        );
+        let text = registry.display(&option).to_string();
+        assert_eq!(text, "backup-servers 10.0.0.1 false 10.0.0.2,10.0.0.3");
+        assert_eq!(registry.parse(&text), Ok(option.clone()));
+        assert_eq!(
+            registry.parse("option 241 10.0.0.1 false 10.0.0.2, 10.0.0.3"),
+            Ok(option.clone())
+        );
+        assert_eq!(
+            registry.parse("Backup Servers 10.0.0.1 false 10.0.0.2"),
+            registry.parse("backup-servers 10.0.0.1 false 10.0.0.2")
+        );
+
+        // Without the registry, the option is only known by its code.
+        assert_eq!(
+            option.to_string(),
+            "option 241 hex 0a:00:00:01:00:0a:00:00:02:0a:00:00:03"
+        );
+        assert!("backup-servers 10.0.0.1 false 10.0.0.2"
+            .parse::<DhcpOption>()
+            .is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Vendor Specific Information (option 43), whose contents depend on the vendor class
+//! (option 60) the client identifies itself with.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::options::*;
+use crate::packet::{DecodeErrorKind, Packet};
+
+// PXE SUB-OPTIONS (Intel PXE specification)
+pub const PXE_DISCOVERY_CONTROL: u8 = 6;
+pub const PXE_BOOT_SERVERS: u8 = 8;
+pub const PXE_BOOT_MENU: u8 = 9;
+pub const PXE_MENU_PROMPT: u8 = 10;
+
+/// List of CAPWAP controller addresses for Cisco access points.
+pub const CISCO_CAPWAP_CONTROLLERS: u8 = 241;
+
+/// Controller address for Ubiquiti devices.
+pub const UBIQUITI_CONTROLLER: u8 = 1;
+
+/// Why sub-options could not be encoded as option 43.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EncodeError {
+    /// There are no sub-options, and option 43 may not be empty.
+    NoSubOptions,
+    /// The data of sub-option `code` is longer than its length octet allows.
+    SubOptionTooLong { code: u8, len: usize },
+    /// The vendor's format has no way to carry sub-option `code`.
+    UnsupportedSubOption(u8),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSubOptions => f.write_str("no vendor sub-options to encode"),
+            Self::SubOptionTooLong { code, len } => {
+                write!(f, "vendor sub-option {} is {} octets long", code, len)
+            }
+            Self::UnsupportedSubOption(code) => write!(f, "unsupported vendor sub-option {}", code),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Translates between the option 43 payload of a vendor and its sub-options.
+pub trait VendorCodec: Send + Sync {
+    fn decode(&self, data: &[u8]) -> Result<Vec<RawDhcpOption>, DecodeErrorKind>;
+    fn encode(&self, sub_options: &[RawDhcpOption]) -> Result<Vec<u8>, EncodeError>;
+}
+
+/// The encapsulation RFC 2132 (section 8.4) recommends: code, length and data for each
+/// sub-option, as for the options themselves. PXE, Cisco and Ubiquiti all use it.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TlvCodec;
+
+impl VendorCodec for TlvCodec {
+    fn decode(&self, data: &[u8]) -> Result<Vec<RawDhcpOption>, DecodeErrorKind> {
+        let mut sub_options = Vec::new();
+        let mut input = data;
+        while let Some((&code, rest)) = input.split_first() {
+            match code {
+                0 => input = rest,
+                255 => break,
+                _ => {
+                    let len = match rest.first() {
+                        Some(&len) if rest.len() > len as usize => len as usize,
+                        _ => return Err(DecodeErrorKind::InvalidLength(data.len())),
+                    };
+                    sub_options.push(RawDhcpOption {
+                        code,
+                        data: rest[1..1 + len].to_vec(),
+                    });
+                    input = &rest[1 + len..];
+                }
+            }
+        }
+        Ok(sub_options)
+    }
+
+    fn encode(&self, sub_options: &[RawDhcpOption]) -> Result<Vec<u8>, EncodeError> {
+        if sub_options.is_empty() {
+            return Err(EncodeError::NoSubOptions);
+        }
+        let mut v = Vec::new();
+        for sub in sub_options {
+            if sub.data.len() > 255 {
+                return Err(EncodeError::SubOptionTooLong {
+                    code: sub.code,
+                    len: sub.data.len(),
+                });
+            }
+            v.push(sub.code);
+            v.push(sub.data.len() as u8);
+            v.extend(&sub.data);
+        }
+        Ok(v)
+    }
+}
+
+/// Codecs for option 43 by vendor class. A class is matched by its longest registered
+/// prefix, since clients append details such as the model ("Cisco AP c3600") or the
+/// architecture ("PXEClient:Arch:00007:..."). Classes without a codec use `TlvCodec`.
+#[derive(Clone, Default)]
+pub struct VendorRegistry {
+    codecs: Vec<(Vec<u8>, Arc<dyn VendorCodec>)>,
+}
+
+impl VendorRegistry {
+    pub fn new() -> VendorRegistry {
+        VendorRegistry::default()
+    }
+
+    /// Uses `codec` for vendor classes starting with `class_prefix`, replacing any
+    /// codec registered for the same prefix.
+    pub fn register<C: VendorCodec + 'static>(&mut self, class_prefix: &[u8], codec: C) {
+        self.codecs.retain(|(prefix, _)| prefix != class_prefix);
+        self.codecs.push((class_prefix.to_vec(), Arc::new(codec)));
+    }
+
+    pub fn codec(&self, vendor_class: &[u8]) -> &dyn VendorCodec {
+        self.codecs
+            .iter()
+            .filter(|(prefix, _)| vendor_class.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(&TlvCodec, |(_, codec)| codec.as_ref())
+    }
+
+    /// Sub-options of the packet's option 43, read as its vendor class dictates.
+    pub fn decode(&self, packet: &Packet) -> Option<Result<Vec<RawDhcpOption>, DecodeErrorKind>> {
+        let data = match packet.option(VENDOR_SPECIFIC_INFORMATION)? {
+            DhcpOption::VendorSpecificInformation(data) => data,
+            _ => return None,
+        };
+        let class = packet.vendor_class().unwrap_or(&[]);
+        Some(self.codec(class).decode(data))
+    }
+
+    /// Option 43 carrying `sub_options` for a client of `vendor_class`. Fails with
+    /// `NoSubOptions` if the codec leaves it empty.
+    pub fn encode(
+        &self,
+        vendor_class: &[u8],
+        sub_options: &[RawDhcpOption],
+    ) -> Result<DhcpOption, EncodeError> {
+        let data = self.codec(vendor_class).encode(sub_options)?;
+        if data.is_empty() {
+            return Err(EncodeError::NoSubOptions);
+        }
+        Ok(DhcpOption::VendorSpecificInformation(data))
+    }
+}
+
+impl fmt::Debug for VendorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.codecs
+                    .iter()
+                    .map(|(prefix, _)| String::from_utf8_lossy(prefix)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use crate::packet::HardwareAddr;
+
+    /// Aruba access points take the controller address as a plain string.
+    struct ArubaCodec;
+
+    impl VendorCodec for ArubaCodec {
+        fn decode(&self, data: &[u8]) -> Result<Vec<RawDhcpOption>, DecodeErrorKind> {
+            Ok(vec![RawDhcpOption {
+                code: 1,
+                data: data.to_vec(),
+            }])
+        }
+
+        fn encode(&self, sub_options: &[RawDhcpOption]) -> Result<Vec<u8>, EncodeError> {
+            match sub_options {
+                [sub] if sub.code == 1 => Ok(sub.data.clone()),
+                [sub, ..] if sub.code != 1 => Err(EncodeError::UnsupportedSubOption(sub.code)),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn controller() -> Vec<RawDhcpOption> {
+        vec![RawDhcpOption {
+            code: UBIQUITI_CONTROLLER,
+            data: vec![10, 0, 0, 5],
+        }]
+    }
+
+    #[test]
+    fn tlv_sub_options() {
+        let data = [1, 4, 10, 0, 0, 5, 0, 241, 0, 255, 9, 9];
+        let subs = TlvCodec.decode(&data).unwrap();
+        assert_eq!(subs.len(), 2);
+        assert_eq!(subs[0], controller()[0]);
+        assert_eq!(subs[1].data, Vec::<u8>::new());
+        assert_eq!(TlvCodec.encode(&controller()).unwrap(), &data[..6]);
+        assert_eq!(
+            TlvCodec.decode(&[1, 4, 10]),
+            Err(DecodeErrorKind::InvalidLength(3))
+        );
+
+        assert_eq!(TlvCodec.encode(&[]), Err(EncodeError::NoSubOptions));
+        let long = RawDhcpOption {
+            code: 9,
+            data: vec![0; 256],
+        };
+        assert_eq!(
+            TlvCodec.encode(&[long]),
+            Err(EncodeError::SubOptionTooLong { code: 9, len: 256 })
+        );
+    }
+
+    #[test]
+    fn codec_chosen_by_vendor_class() {
+        let mut registry = VendorRegistry::new();
+        registry.register(b"Aruba", TlvCodec);
+        registry.register(b"ArubaAP", ArubaCodec);
+
+        let aruba = registry.encode(b"ArubaAP", &controller()).unwrap();
+        assert_eq!(
+            aruba,
+            DhcpOption::VendorSpecificInformation(vec![10, 0, 0, 5])
+        );
+        let other = registry.encode(b"ubnt", &controller()).unwrap();
+        assert_eq!(
+            other,
+            DhcpOption::VendorSpecificInformation(vec![1, 4, 10, 0, 0, 5])
+        );
+
+        let p = PacketBuilder::new(HardwareAddr::ethernet([0; 6]))
+            .option(DhcpOption::VendorClassIdentifier(b"ArubaAP".to_vec()))
+            .option(aruba)
+            .build();
+        assert_eq!(registry.decode(&p), Some(Ok(controller())));
+
+        // An empty option 43 is never sent, whatever the codec makes of the sub-options.
+        assert_eq!(
+            registry.encode(b"ArubaAP", &[]),
+            Err(EncodeError::NoSubOptions)
+        );
+        assert_eq!(
+            registry.encode(
+                b"ArubaAP",
+                &[RawDhcpOption {
+                    code: 2,
+                    data: vec![]
+                }]
+            ),
+            Err(EncodeError::UnsupportedSubOption(2))
+        );
+        assert_eq!(format!("{:?}", registry), r#"["Aruba", "ArubaAP"]"#);
+    }
+}